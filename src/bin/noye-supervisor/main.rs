@@ -0,0 +1,24 @@
+use noye::Shutdown;
+
+const CONFIG_LOCATION: &str = "noye.toml";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    std::env::set_var("RUST_LOG", "noye=trace");
+
+    let opts = alto_logger::Options::default()
+        .with_time(alto_logger::options::TimeConfig::date_time_format("%c"));
+    let file = alto_logger::FileLogger::timestamp(opts.clone(), "noye-supervisor.log")?;
+
+    let logger = alto_logger::MultiLogger::new()
+        .with(alto_logger::TermLogger::new(opts.clone())?)
+        .with(file);
+    alto_logger::init(logger).expect("init logger");
+
+    let config = noye::Config::load(CONFIG_LOCATION).await?;
+
+    let shutdown = Shutdown::default();
+    tokio::spawn(shutdown.clone().stop_on_signal());
+
+    noye::daemon::run(config.modules.restart, shutdown).await
+}
@@ -1,7 +1,8 @@
-use noye::{Runner, WriterResponder};
-use tokio::{io::BufStream, net::TcpStream, prelude::*, sync::mpsc};
+use noye::{connection::Supervisor, JobContext, Runner, Shutdown, WriterResponder};
+use tokio::sync::mpsc;
 
 const CONFIG_LOCATION: &str = "noye.toml";
+const TEMPLATES_LOCATION: &str = "templates.toml";
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -18,67 +19,71 @@ async fn main() -> anyhow::Result<()> {
     alto_logger::init(logger).expect("init logger");
 
     let config = noye::Config::load(CONFIG_LOCATION).await?;
-
-    let noye::config::Irc {
-        name,
-        real,
-        user,
-        address,
-        ..
-    } = &config.irc_config;
-
-    let (tx, mut rx) = mpsc::channel::<String>(64);
-
-    let mut writer = noye::Writer(tx.clone());
-    writer.raw(format!("NICK {}", &name)).await?;
-    writer.raw(format!("USER {} * 8 :{}", &user, &real)).await?;
-
-    let mut stream = BufStream::new(TcpStream::connect(&address).await?);
+    noye::daemon::report_crash(&config.modules.restart.crash_log);
 
     let mut init = noye::modules::ModuleInit::default();
 
-    init.state
-        .insert(noye::CachedConfig::new(config, CONFIG_LOCATION));
+    let networks = config
+        .networks()
+        .into_iter()
+        .map(|irc| irc.network.clone())
+        .collect::<Vec<_>>();
+    let config = noye::CachedConfig::new(config, CONFIG_LOCATION);
+    init.state.insert(config.clone());
     init.state.insert(noye::LogFile(log_file));
 
+    let shutdown = Shutdown::default();
+    init.state.insert(shutdown.clone());
+    tokio::spawn(shutdown.clone().stop_on_signal());
+
+    let resolver = noye::resolver::new(noye::TemplateStore::new(
+        noye::DEFAULT_TEMPLATES,
+        TEMPLATES_LOCATION,
+    )?);
+    init.state.insert(resolver.clone());
+
     // TODO configure this
-    let temp = noye::http::server::TempStore::default();
-    temp.start_culling();
-    init.state.insert(temp);
+    init.state.insert(noye::http::server::TempStore::default());
 
     noye::modules::initialize_modules(&mut init).await?;
     let noye::modules::ModuleInit {
         commands,
         passives,
+        events,
+        scheduler,
         state,
         ..
     } = init;
 
-    let mut runner = Runner::new(state, writer, commands, passives);
-    let responder = WriterResponder::new(
-        tx,
-        noye::resolver::new(template::MemoryStore::new(
-            noye::DEFAULT_TEMPLATES, //
-            template::load_toml,
-        ))?,
-    );
-
-    let quit = runner.quit.clone();
-    let mut string = String::new();
-    loop {
-        tokio::select! {
-            Ok(_) = stream.read_line(&mut string) => {
-                runner.handle(&string, responder.clone()).await?;
-                string.clear();
-            }
-            Some(data) = rx.recv() => {
-                stream.write_all(data.as_bytes()).await?;
-                stream.flush().await?;
-            }
-            _ = quit.notified() => break,
-            else => break
-        }
+    // the networks share the modules, but each one gets its own state and connection
+    let (mut states, mut writers, mut supervisors) = (vec![], vec![], vec![]);
+    let mut jobs = vec![];
+    for network in networks {
+        let mut state = state.fork();
+        state.insert(config.for_network(network.as_deref())?);
+
+        let (tx, rx) = mpsc::channel::<String>(64);
+        let runner = Runner::new(
+            state,
+            noye::Writer(tx.clone()),
+            commands.clone(),
+            passives.clone(),
+            events.clone(),
+        );
+        states.push(runner.state.clone());
+        writers.push(runner.writer.clone());
+        jobs.push(JobContext {
+            state: runner.state.clone(),
+            writer: runner.writer.clone(),
+            network: runner.network.clone(),
+        });
+
+        let responder = WriterResponder::new(tx, resolver.clone());
+        supervisors.push(Supervisor::new(runner, responder, rx).run());
     }
+    drop(config);
 
-    Ok(())
+    noye::config_watcher::start(states, writers).await?;
+    scheduler.start(jobs, shutdown)?;
+    futures::future::try_join_all(supervisors).await.map(drop)
 }
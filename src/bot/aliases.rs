@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Other names for commands, these are expanded before the command is looked up
+///
+/// An alias can include arguments, e.g. `p` -> `pictures refresh`. Clones share the same
+/// aliases, so every network sees the changes
+#[derive(Default, Debug, Clone)]
+pub struct Aliases {
+    map: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl Aliases {
+    pub fn insert(&self, name: impl ToString, command: impl ToString) {
+        self.map
+            .write()
+            .unwrap()
+            .insert(name.to_string(), command.to_string());
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.map.write().unwrap().remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.map.read().unwrap().get(name).cloned()
+    }
+
+    /// Expands the alias at the start of `invocation`, if there is one
+    pub fn resolve(&self, invocation: &str) -> Option<String> {
+        let mut iter = invocation.splitn(2, ' ');
+        let command = self.get(iter.next()?)?;
+        match iter.next() {
+            Some(rest) => Some(format!("{} {}", command, rest)),
+            None => Some(command),
+        }
+    }
+}
+
+impl<K: ToString, V: ToString> std::iter::FromIterator<(K, V)> for Aliases {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let aliases = Self::default();
+        for (name, command) in iter {
+            aliases.insert(name, command);
+        }
+        aliases
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let aliases = vec![("p", "pictures"), ("yt", "youtube search")]
+            .into_iter()
+            .collect::<Aliases>();
+
+        assert_eq!(aliases.resolve("p").as_deref(), Some("pictures"));
+        assert_eq!(
+            aliases.resolve("p refresh").as_deref(),
+            Some("pictures refresh")
+        );
+        assert_eq!(
+            aliases.resolve("yt some video").as_deref(),
+            Some("youtube search some video")
+        );
+        assert_eq!(aliases.resolve("pictures"), None);
+        assert_eq!(aliases.resolve("P"), None);
+
+        assert!(aliases.remove("p"));
+        assert!(!aliases.remove("p"));
+        assert_eq!(aliases.resolve("p"), None);
+    }
+}
@@ -0,0 +1,425 @@
+use super::Role;
+use crate::responses::Usage;
+
+use std::collections::{BTreeMap, HashMap};
+use tokio::time::Duration;
+
+/// The kinds of values an argument can have
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Kind {
+    /// A single word
+    Word,
+    /// The rest of the line, this has to be the last argument
+    Text,
+    Number,
+    /// A duration, like `30s` or `1h`
+    Duration,
+    Channel,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Word => write!(f, "word"),
+            Self::Text => write!(f, "text"),
+            Self::Number => write!(f, "number"),
+            Self::Duration => write!(f, "duration"),
+            Self::Channel => write!(f, "channel"),
+        }
+    }
+}
+
+impl Kind {
+    fn parse(self, input: &str) -> Option<Value> {
+        let value = match self {
+            Self::Word | Self::Text => Value::Text(input.to_string()),
+            Self::Number => Value::Number(input.parse().ok()?),
+            Self::Duration => Value::Duration(Duration::from_secs(
+                simple_duration_parse::parse_secs(input).ok()?,
+            )),
+            Self::Channel if input.starts_with(crate::irc::CHANNEL_TYPES) => {
+                Value::Text(input.to_string())
+            }
+            Self::Channel => return None,
+        };
+        Some(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(u64),
+    Duration(Duration),
+}
+
+#[derive(Debug, Clone)]
+struct Arg {
+    name: String,
+    kind: Kind,
+    optional: bool,
+}
+
+/// What a command expects after its name, this is also used to generate `!help`
+#[derive(Default, Debug, Clone)]
+pub struct Args {
+    description: Option<String>,
+    args: Vec<Arg>,
+    subcommands: Vec<(String, Args)>,
+    require_subcommand: bool,
+}
+
+impl Args {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A short description of the command, used by `!help`
+    pub fn description(mut self, description: impl ToString) -> Self {
+        self.description.replace(description.to_string());
+        self
+    }
+
+    pub fn arg(self, name: impl ToString, kind: Kind) -> Self {
+        self.push(name, kind, false)
+    }
+
+    /// An argument that can be left out, these have to come after the required ones
+    pub fn optional(self, name: impl ToString, kind: Kind) -> Self {
+        self.push(name, kind, true)
+    }
+
+    /// A subcommand, this is matched before any of the arguments
+    pub fn subcommand(mut self, name: impl ToString, args: Args) -> Self {
+        self.subcommands.push((name.to_string(), args));
+        self
+    }
+
+    /// Don't allow the command to be used without one of its subcommands
+    pub fn require_subcommand(mut self) -> Self {
+        self.require_subcommand = true;
+        self
+    }
+
+    fn push(mut self, name: impl ToString, kind: Kind, optional: bool) -> Self {
+        self.args.push(Arg {
+            name: name.to_string(),
+            kind,
+            optional,
+        });
+        self
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// A one line summary of how to use `command`, e.g. `join <channel> [key]`
+    pub fn usage(&self, command: &str) -> String {
+        let mut usage = command.to_string();
+        if !self.subcommands.is_empty() {
+            let names = self
+                .subcommands
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join("|");
+            if self.require_subcommand {
+                usage.push_str(&format!(" <{}>", names));
+            } else {
+                usage.push_str(&format!(" [{}]", names));
+            }
+        }
+
+        for arg in &self.args {
+            let dots = if arg.kind == Kind::Text { "..." } else { "" };
+            if arg.optional {
+                usage.push_str(&format!(" [{}{}]", arg.name, dots));
+            } else {
+                usage.push_str(&format!(" <{}{}>", arg.name, dots));
+            }
+        }
+        usage
+    }
+
+    /// The usage of `command` and each of its subcommands
+    pub fn usages(&self, command: &str) -> Vec<String> {
+        let mut usages = vec![];
+        if !self.require_subcommand || self.subcommands.is_empty() {
+            usages.push(self.usage(command))
+        }
+        for (name, args) in &self.subcommands {
+            usages.extend(args.usages(&format!("{} {}", command, name)));
+        }
+        usages
+    }
+
+    /// Parses the arguments given to `command`
+    ///
+    /// The error is the response that should be sent to the user
+    pub(crate) fn parse(&self, command: &str, input: &[&str]) -> Result<Parsed, Usage> {
+        let input = input
+            .iter()
+            .copied()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+
+        let mut parsed = Parsed::default();
+        self.parse_into(command, &input, &mut parsed)?;
+        Ok(parsed)
+    }
+
+    fn parse_into(&self, command: &str, input: &[&str], parsed: &mut Parsed) -> Result<(), Usage> {
+        if !self.subcommands.is_empty() {
+            let head = input.first();
+            let found = head.and_then(|head| {
+                self.subcommands
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(head))
+            });
+
+            match (head, found) {
+                (Some(..), Some((name, args))) => {
+                    parsed.subcommand.replace(name.clone());
+                    let command = format!("{} {}", command, name);
+                    return args.parse_into(&command, &input[1..], parsed);
+                }
+                (Some(head), None) if self.args.is_empty() => {
+                    return Err(Usage::UnknownSubcommand {
+                        subcommand: head.to_string(),
+                        usage: self.usage(command),
+                    });
+                }
+                (None, _) if self.require_subcommand => {
+                    return Err(Usage::Missing {
+                        arg: "subcommand".into(),
+                        usage: self.usage(command),
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        let mut rest = input;
+        for arg in &self.args {
+            let value = match (arg.kind, rest) {
+                (_, []) if arg.optional => break,
+                (_, []) => {
+                    return Err(Usage::Missing {
+                        arg: arg.name.clone(),
+                        usage: self.usage(command),
+                    })
+                }
+                (Kind::Text, input) => {
+                    rest = &[];
+                    input.join(" ")
+                }
+                (_, input) => {
+                    rest = &input[1..];
+                    input[0].to_string()
+                }
+            };
+
+            match arg.kind.parse(&value) {
+                Some(parsed_value) => {
+                    parsed.values.insert(arg.name.clone(), parsed_value);
+                }
+                None => {
+                    return Err(Usage::Invalid {
+                        arg: arg.name.clone(),
+                        value,
+                        kind: arg.kind.to_string(),
+                        usage: self.usage(command),
+                    })
+                }
+            }
+        }
+
+        if !rest.is_empty() {
+            return Err(Usage::TooMany {
+                usage: self.usage(command),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The arguments a command was given, after they were checked against its `Args`
+#[derive(Default, Debug, Clone)]
+pub struct Parsed {
+    subcommand: Option<String>,
+    values: HashMap<String, Value>,
+}
+
+impl Parsed {
+    pub fn subcommand(&self) -> Option<&str> {
+        self.subcommand.as_deref()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// Gets a `Word`, `Text` or `Channel` argument
+    pub fn str(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self, name: &str) -> Option<u64> {
+        match self.get(name)? {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self, name: &str) -> Option<Duration> {
+        match self.get(name)? {
+            Value::Duration(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// Like `str`, but a missing argument is an error
+    pub fn expect_str(&self, name: &str) -> anyhow::Result<&str> {
+        self.str(name)
+            .ok_or_else(|| anyhow::anyhow!("missing argument: {}", name))
+    }
+}
+
+/// The commands that have been added, for `!help`
+#[derive(Default, Debug, Clone)]
+pub struct CommandIndex {
+    commands: BTreeMap<String, (Option<Role>, Args)>,
+}
+
+impl CommandIndex {
+    pub(super) fn insert(&mut self, command: &str, role: Option<Role>, args: Args) {
+        self.commands.insert(command.to_string(), (role, args));
+    }
+
+    /// The commands someone with `role` can use
+    pub fn available(&self, role: Option<Role>) -> impl Iterator<Item = &str> + '_ {
+        self.commands
+            .iter()
+            .filter(move |(_, (required, _))| *required <= role)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn get(&self, command: &str) -> Option<(Option<Role>, &Args)> {
+        self.commands.get(command).map(|(role, args)| (*role, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module() -> Args {
+        let toggle = Args::new()
+            .arg("module", Kind::Word)
+            .optional("channel", Kind::Channel);
+        Args::new()
+            .subcommand("enable", toggle.clone())
+            .subcommand("disable", toggle)
+            .require_subcommand()
+    }
+
+    #[test]
+    fn usage() {
+        let args = Args::new()
+            .arg("channel", Kind::Channel)
+            .optional("reason", Kind::Text);
+        assert_eq!(args.usage("part"), "part <channel> [reason...]");
+        assert_eq!(args.usages("part"), vec!["part <channel> [reason...]"]);
+
+        assert_eq!(module().usage("module"), "module <enable|disable>");
+        assert_eq!(
+            module().usages("module"),
+            vec![
+                "module enable <module> [channel]",
+                "module disable <module> [channel]",
+            ]
+        );
+
+        let args = Args::new().subcommand("refresh", Args::new());
+        assert_eq!(
+            args.usages("pictures"),
+            vec!["pictures [refresh]", "pictures refresh"]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let args = Args::new()
+            .arg("delay", Kind::Number)
+            .optional("wait", Kind::Duration)
+            .optional("reason", Kind::Text);
+
+        let parsed = args
+            .parse("test", &["15", "1m", "some", "", "reason"])
+            .unwrap();
+        assert_eq!(parsed.number("delay"), Some(15));
+        assert_eq!(parsed.duration("wait"), Some(Duration::from_secs(60)));
+        assert_eq!(parsed.str("reason"), Some("some reason"));
+
+        let parsed = args.parse("test", &["15"]).unwrap();
+        assert_eq!(parsed.number("delay"), Some(15));
+        assert!(parsed.get("wait").is_none());
+
+        let parsed = module().parse("module", &["DISABLE", "pictures"]).unwrap();
+        assert_eq!(parsed.subcommand(), Some("disable"));
+        assert_eq!(parsed.str("module"), Some("pictures"));
+        assert!(parsed.str("channel").is_none());
+
+        for channel in &["#test", "&test", "+test", "!test"] {
+            let parsed = module().parse("module", &["enable", "pictures", channel]);
+            assert_eq!(parsed.unwrap().str("channel"), Some(*channel));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let args = Args::new().arg("delay", Kind::Number);
+        assert!(matches!(
+            args.parse("test", &[]),
+            Err(Usage::Missing { arg, .. }) if arg == "delay"
+        ));
+        assert!(matches!(
+            args.parse("test", &["soon"]),
+            Err(Usage::Invalid { value, kind, .. }) if value == "soon" && kind == "number"
+        ));
+        assert!(matches!(
+            args.parse("test", &["1", "2"]),
+            Err(Usage::TooMany { .. })
+        ));
+
+        assert!(matches!(
+            module().parse("module", &[]),
+            Err(Usage::Missing { arg, .. }) if arg == "subcommand"
+        ));
+        assert!(matches!(
+            module().parse("module", &["toggle"]),
+            Err(Usage::UnknownSubcommand { subcommand, .. }) if subcommand == "toggle"
+        ));
+        assert!(matches!(
+            module().parse("module", &["enable", "pictures", "test"]),
+            Err(Usage::Invalid { usage, .. }) if usage == "module enable <module> [channel]"
+        ));
+    }
+
+    #[test]
+    fn index() {
+        let mut index = CommandIndex::default();
+        index.insert("join", Some(Role::Owner), Args::new());
+        index.insert("help", None, Args::new());
+        index.insert("ignore", Some(Role::Admin), Args::new());
+
+        let available = |role| index.available(role).collect::<Vec<_>>();
+        assert_eq!(available(None), vec!["help"]);
+        assert_eq!(available(Some(Role::Admin)), vec!["help", "ignore"]);
+        assert_eq!(available(Some(Role::Owner)), vec!["help", "ignore", "join"]);
+    }
+}
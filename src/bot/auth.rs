@@ -0,0 +1,224 @@
+use super::{JoinedChannels, Ready, State, Writer};
+use crate::config::Auth;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuthStatus {
+    Pending,
+    Authenticated,
+    Failed,
+}
+
+/// Tracks the authentication for the current connection
+///
+/// Channels are joined once we're registered and the authentication has finished (or failed)
+#[derive(Debug, Clone)]
+pub struct Authenticator {
+    status: AuthStatus,
+    account: Option<String>,
+    registered: bool,
+    joined: bool,
+    attempt: u64,
+}
+
+impl Default for Authenticator {
+    fn default() -> Self {
+        Self {
+            status: AuthStatus::Pending,
+            account: None,
+            registered: false,
+            joined: false,
+            attempt: 0,
+        }
+    }
+}
+
+impl Authenticator {
+    pub fn status(&self) -> AuthStatus {
+        self.status
+    }
+
+    /// The account name the server reported we're logged in as
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// This is incremented each time the connection is reset
+    pub(super) fn attempt(&self) -> u64 {
+        self.attempt
+    }
+
+    pub(super) fn reset(&mut self) {
+        *self = Self {
+            attempt: self.attempt + 1,
+            ..Self::default()
+        }
+    }
+
+    pub(super) fn set_account(&mut self, account: impl ToString) {
+        self.account.replace(account.to_string());
+    }
+
+    /// Marks the connection as registered, returning whether we should join the channels now
+    pub(super) fn register(&mut self) -> bool {
+        self.registered = true;
+        self.should_join()
+    }
+
+    /// Completes a pending authentication, returning whether we should join the channels now
+    pub(super) fn complete(&mut self, status: AuthStatus) -> bool {
+        if self.status != AuthStatus::Pending {
+            return false;
+        }
+        self.status = status;
+        self.should_join()
+    }
+
+    fn should_join(&mut self) -> bool {
+        if !self.registered || self.joined || self.status == AuthStatus::Pending {
+            return false;
+        }
+        self.joined = true;
+        true
+    }
+}
+
+pub(super) fn sasl_mechanism(auth: &Auth) -> Option<&'static str> {
+    match auth {
+        Auth::SaslPlain { .. } => Some("PLAIN"),
+        Auth::SaslExternal => Some("EXTERNAL"),
+        _ => None,
+    }
+}
+
+/// Creates the `AUTHENTICATE` lines for the configured SASL mechanism
+pub(super) fn sasl_payload(auth: &Auth) -> Vec<String> {
+    // the payload is sent in 400 byte chunks. if the last chunk is exactly 400 bytes, a '+' must follow
+    const CHUNK: usize = 400;
+
+    let payload = match auth {
+        Auth::SaslPlain { name, pass } => base64::encode(format!("{}\0{}\0{}", name, name, pass)),
+        _ => return vec!["AUTHENTICATE +".into()],
+    };
+
+    let mut lines = payload
+        .as_bytes()
+        .chunks(CHUNK)
+        .map(|chunk| format!("AUTHENTICATE {}", String::from_utf8_lossy(chunk)))
+        .collect::<Vec<_>>();
+    if payload.len() % CHUNK == 0 {
+        lines.push("AUTHENTICATE +".into())
+    }
+    lines
+}
+
+/// Sends the authentication request for the methods that happen after registration
+pub(super) async fn start(auth: &Auth, nick: &str, writer: &mut Writer) -> anyhow::Result<()> {
+    match auth {
+        Auth::Q { name, pass } => {
+            log::info!("authing with Q");
+            writer
+                .raw(format!(
+                    "PRIVMSG Q@CServe.quakenet.org :AUTH {} {}",
+                    name, pass
+                ))
+                .await?;
+            writer.raw(format!("MODE {} +x", nick)).await
+        }
+        Auth::NickServ { name, pass } => {
+            log::info!("identifying with NickServ");
+            let line = match name {
+                Some(name) => format!("PRIVMSG NickServ :IDENTIFY {} {}", name, pass),
+                None => format!("PRIVMSG NickServ :IDENTIFY {}", pass),
+            };
+            writer.raw(line).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Completes the authentication if it was pending, joining the channels if we're registered
+pub(super) async fn complete(
+    state: &mut State,
+    writer: &mut Writer,
+    status: AuthStatus,
+) -> anyhow::Result<()> {
+    if state.expect_get_mut::<Authenticator>()?.complete(status) {
+        if status == AuthStatus::Failed {
+            log::warn!("authentication did not succeed, joining channels");
+        }
+        join_channels(state, writer).await?;
+    }
+    Ok(())
+}
+
+/// Joins the configured channels and any channels we were in before a reconnect
+///
+/// The connection is ready for the scheduled jobs after this
+pub(super) async fn join_channels(state: &mut State, writer: &mut Writer) -> anyhow::Result<()> {
+    let mut channels = state.expect_get::<JoinedChannels>()?.0.clone();
+    channels.extend(state.config()?.irc_config.channels.iter().cloned());
+    for channel in channels {
+        writer.join(channel).await?;
+    }
+    state.expect_get::<Ready>()?.set();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authenticator() {
+        let mut auth = Authenticator::default();
+        assert!(!auth.complete(AuthStatus::Authenticated));
+        assert!(auth.register());
+        assert!(!auth.register());
+        assert!(!auth.complete(AuthStatus::Failed));
+        assert_eq!(auth.status(), AuthStatus::Authenticated);
+
+        let mut auth = Authenticator::default();
+        assert!(!auth.register());
+        assert!(auth.complete(AuthStatus::Failed));
+        assert!(!auth.complete(AuthStatus::Authenticated));
+        assert_eq!(auth.status(), AuthStatus::Failed);
+
+        auth.reset();
+        assert_eq!(auth.attempt(), 1);
+        assert_eq!(auth.status(), AuthStatus::Pending);
+        assert!(!auth.complete(AuthStatus::Authenticated));
+        assert!(auth.register());
+    }
+
+    #[test]
+    fn sasl_plain() {
+        let auth = Auth::SaslPlain {
+            name: "noye".into(),
+            pass: "hunter2".into(),
+        };
+        assert_eq!(sasl_mechanism(&auth), Some("PLAIN"));
+        assert_eq!(
+            sasl_payload(&auth),
+            vec![format!(
+                "AUTHENTICATE {}",
+                base64::encode("noye\0noye\0hunter2")
+            )]
+        );
+
+        let auth = Auth::SaslPlain {
+            name: "a".repeat(100),
+            pass: "b".repeat(98),
+        };
+        let lines = sasl_payload(&auth);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), "AUTHENTICATE ".len() + 400);
+        assert_eq!(lines[1], "AUTHENTICATE +");
+    }
+
+    #[test]
+    fn sasl_external() {
+        let auth = Auth::SaslExternal;
+        assert_eq!(sasl_mechanism(&auth), Some("EXTERNAL"));
+        assert_eq!(sasl_payload(&auth), vec!["AUTHENTICATE +"]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashSet;
+
+/// IRCv3 capabilities negotiated with the server
+#[derive(Default, Debug, Clone)]
+pub struct Capabilities {
+    offered: Vec<String>,
+    enabled: HashSet<String>,
+}
+
+impl Capabilities {
+    pub fn is_enabled(&self, cap: &str) -> bool {
+        self.enabled.contains(cap)
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &str> + '_ {
+        self.enabled.iter().map(|s| s.as_str())
+    }
+
+    /// Adds the capabilities from a `CAP LS` line
+    pub(super) fn offer(&mut self, list: &str) {
+        // 302 allows for values, e.g. `sasl=PLAIN,EXTERNAL`
+        self.offered.extend(
+            list.split_whitespace()
+                .map(|cap| cap.splitn(2, '=').next().unwrap().to_string()),
+        )
+    }
+
+    /// Returns the capabilities we want that were offered, and resets the offer
+    pub(super) fn take_wanted(&mut self, wanted: &[String]) -> Vec<String> {
+        let offered = std::mem::take(&mut self.offered);
+        wanted
+            .iter()
+            .filter(|cap| offered.contains(cap))
+            .cloned()
+            .collect()
+    }
+
+    /// Applies the capabilities from a `CAP ACK` or `CAP DEL` line
+    pub(super) fn acknowledge(&mut self, list: &str) {
+        for cap in list.split_whitespace() {
+            if cap.starts_with('-') {
+                self.enabled.remove(&cap[1..]);
+                continue;
+            }
+            log::debug!("enabled capability: {}", cap);
+            self.enabled.insert(cap.to_string());
+        }
+    }
+
+    pub(super) fn remove(&mut self, list: &str) {
+        for cap in list.split_whitespace() {
+            log::debug!("capability was removed: {}", cap);
+            self.enabled.remove(cap);
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use super::{
+    permissions::{Identity, Role},
+    responder::resolve_template,
+    Resolver, State, Tracker, Writer,
+};
+use crate::{config::ConfigChanged, responses, util::inspect_err, CachedConfig, Config};
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::{
+    sync::{broadcast::RecvError, Mutex},
+    time::Duration,
+};
+
+/// How often the config file is checked for changes
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Starts watching the config file for every network, and following the channels listed in it
+pub async fn start(states: Vec<Arc<Mutex<State>>>, writers: Vec<Writer>) -> anyhow::Result<()> {
+    for (state, writer) in states.iter().zip(writers.iter().cloned()) {
+        let (network, changes) = {
+            let state = state.lock().await;
+            let config = state.expect_get::<CachedConfig>()?;
+            (
+                config.network().map(ToString::to_string),
+                config.subscribe(),
+            )
+        };
+
+        tokio::spawn(async move {
+            if let Err(err) = follow_channels(network, changes, writer).await {
+                inspect_err(&err, || "following the configured channels");
+            }
+        });
+    }
+
+    tokio::spawn(async move {
+        if let Err(err) = watch(states, writers).await {
+            inspect_err(&err, || "watching the config");
+        }
+    });
+    Ok(())
+}
+
+/// Watches the config and templates files, swapping in the new config when it changes
+///
+/// This polls the files' modification times every few seconds rather than asking to be notified,
+/// so an edit can take that long to be noticed. If the new config is invalid the old one is kept,
+/// and any owners we can see are told why
+pub async fn watch(states: Vec<Arc<Mutex<State>>>, mut writers: Vec<Writer>) -> anyhow::Result<()> {
+    let (path, resolver) = match states.first() {
+        Some(state) => {
+            let state = state.lock().await;
+            let path = state.expect_get::<CachedConfig>()?.path().to_path_buf();
+            (path, state.get::<Resolver>().cloned())
+        }
+        None => return Ok(()),
+    };
+
+    let mut config = Watched::new(path).await;
+    // the networks share the templates
+    let mut templates = match &resolver {
+        Some(resolver) => {
+            let path = resolver.lock().await.path().to_path_buf();
+            Some(Watched::new(path).await)
+        }
+        None => None,
+    };
+
+    loop {
+        tokio::time::delay_for(INTERVAL).await;
+
+        if let (Some(resolver), Some(templates)) = (&resolver, &mut templates) {
+            if templates.changed().await {
+                reload_templates(resolver).await;
+            }
+        }
+
+        if !config.changed().await {
+            continue;
+        }
+
+        if let Err(err) = reload(&states).await {
+            log::warn!("cannot reload config: {:#}", err);
+            for (state, writer) in states.iter().zip(writers.iter_mut()) {
+                if let Err(err) = report(state, writer, &err).await {
+                    log::warn!("cannot report the config error: {:#}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Loads the config file, replacing the config for every network if it is valid
+///
+/// Networks can't be removed while running, but any new ones are ignored until a restart
+pub async fn reload(states: &[Arc<Mutex<State>>]) -> anyhow::Result<()> {
+    let path = match states.first() {
+        Some(state) => state
+            .lock()
+            .await
+            .expect_get::<CachedConfig>()?
+            .path()
+            .to_path_buf(),
+        None => return Ok(()),
+    };
+
+    let config = Arc::new(Config::load(&path).await?);
+
+    let mut running = vec![];
+    for state in states {
+        let state = state.lock().await;
+        let network = state.expect_get::<CachedConfig>()?.network();
+        if config.network(network).is_none() {
+            anyhow::bail!(
+                "network '{}' was removed, this needs a restart",
+                network.unwrap_or("default")
+            )
+        }
+        running.push(network.map(ToString::to_string));
+    }
+    for irc in config.networks() {
+        if !running.contains(&irc.network) {
+            log::warn!("network '{}' was added, this needs a restart", irc.name());
+        }
+    }
+
+    let mut old = None;
+    for state in states {
+        let mut state = state.lock().await;
+        old = Some(
+            state
+                .expect_get_mut::<CachedConfig>()?
+                .replace(config.clone())?,
+        );
+    }
+    log::info!("reloaded config from {}", path.display());
+
+    // the networks share their subscribers, so this only has to be done once
+    if let (Some(state), Some(old)) = (states.first(), old) {
+        state
+            .lock()
+            .await
+            .expect_get::<CachedConfig>()?
+            .announce(old);
+    }
+    Ok(())
+}
+
+/// Reloads the templates, the old ones are kept if the file is invalid
+async fn reload_templates(resolver: &Resolver) {
+    let mut store = resolver.lock().await;
+    match store.reload() {
+        Ok(..) => log::info!("reloaded templates from {}", store.path().display()),
+        Err(err) => log::warn!("cannot reload templates: {:#}", err),
+    }
+}
+
+/// A file that is checked for changes
+struct Watched {
+    path: PathBuf,
+    last: Option<SystemTime>,
+}
+
+impl Watched {
+    async fn new(path: PathBuf) -> Self {
+        let last = modified(&path).await;
+        Self { path, last }
+    }
+
+    /// Whether the file has changed since the last check
+    async fn changed(&mut self) -> bool {
+        let current = modified(&self.path).await;
+        if current == self.last {
+            return false;
+        }
+        // a broken file is only tried again once it changes
+        self.last = current;
+        true
+    }
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|md| md.modified())
+        .ok()
+}
+
+/// Sends `err` to the owners in our channels
+///
+/// The state isn't held while sending, the runner needs it to drain the writer
+async fn report(
+    state: &Mutex<State>,
+    writer: &mut Writer,
+    err: &anyhow::Error,
+) -> anyhow::Result<()> {
+    let (resolver, owners) = {
+        let state = state.lock().await;
+        let permissions = state.config()?.irc_config.permissions();
+        let owners = state
+            .expect_get::<Tracker>()?
+            .users()
+            .filter(|user| {
+                let hostmask = user.hostmask();
+                let identity = Identity {
+                    hostmask: hostmask.as_deref(),
+                    account: user.account.as_deref(),
+                };
+                identity.role(&permissions) == Some(Role::Owner)
+            })
+            .map(|user| user.nick.clone())
+            .collect::<Vec<_>>();
+        (state.expect_get::<Resolver>()?.clone(), owners)
+    };
+    if owners.is_empty() {
+        return Ok(());
+    }
+
+    let error = format!("{:#}", err).replace(&['\r', '\n'][..], " ");
+    let resp = resolve_template(resolver, None, responses::Config::Invalid { error }).await?;
+    for owner in owners {
+        writer.raw(format!("NOTICE {} :{}", owner, resp)).await?;
+    }
+    Ok(())
+}
+
+/// Joins the channels added to the config for `network`, and leaves the ones removed from it
+pub async fn follow_channels(
+    network: Option<String>,
+    mut changes: tokio::sync::broadcast::Receiver<ConfigChanged>,
+    mut writer: Writer,
+) -> anyhow::Result<()> {
+    loop {
+        let ConfigChanged { old, new } = match changes.recv().await {
+            Ok(changed) => changed,
+            Err(RecvError::Lagged(skipped)) => {
+                log::warn!("missed {} config changes", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break Ok(()),
+        };
+
+        let channels = |config: &Config| {
+            config
+                .network(network.as_deref())
+                .map(|irc| irc.channels.clone())
+                .unwrap_or_default()
+        };
+        let (old, new) = (&channels(&old), &channels(&new));
+        let contains =
+            |list: &[String], channel: &str| list.iter().any(|c| c.eq_ignore_ascii_case(channel));
+
+        for channel in new.iter().filter(|c| !contains(old, c)) {
+            writer.join(channel).await?;
+        }
+        for channel in old.iter().filter(|c| !contains(new, c)) {
+            writer.part(channel).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::prelude::*;
+
+    fn to_toml(config: &Config) -> String {
+        toml::Value::try_from(config).unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn reload_config() {
+        let path = std::env::temp_dir().join(format!("noye-config-{}.toml", std::process::id()));
+
+        let network = |name: &str, channel: &str| crate::config::Irc {
+            network: Some(name.into()),
+            channels: vec![channel.into()],
+            ..Default::default()
+        };
+
+        let mut config = Config {
+            networks: vec![network("a", "#old"), network("b", "#foo")],
+            ..Default::default()
+        };
+        config.modules.repost.staleness = "1d".into();
+        config.modules.pictures.cooldown = "1m".into();
+        config.modules.pictures.quiet_time = "1h".into();
+
+        let cached = CachedConfig::new(config.clone(), &path);
+        let mut states = vec![];
+        let mut follows = vec![];
+        for name in &["a", "b"] {
+            let cached = cached.for_network(Some(name)).unwrap();
+            let changes = cached.subscribe();
+
+            let mut state = State::default();
+            state.insert(cached);
+            states.push(Arc::new(Mutex::new(state)));
+
+            let (tx, rx) = tokio::sync::mpsc::channel(8);
+            let network = Some(name.to_string());
+            let follow = tokio::spawn(follow_channels(network, changes, Writer(tx)));
+            follows.push((follow, rx));
+        }
+        drop(cached);
+
+        let channels = |state: &State| state.config().unwrap().irc_config.channels.clone();
+
+        config.networks[0].channels = vec!["#new".into()];
+        std::fs::write(&path, to_toml(&config)).unwrap();
+        reload(&states).await.unwrap();
+        assert_eq!(channels(&*states[0].lock().await), vec!["#new"]);
+        assert_eq!(channels(&*states[1].lock().await), vec!["#foo"]);
+
+        // the old config is kept if the new one is invalid
+        let mut invalid = config.clone();
+        invalid.modules.repost.staleness = "soon".into();
+        invalid.networks[0].channels = vec!["#invalid".into()];
+        let mut removed = config.clone();
+        removed.networks.truncate(1);
+        removed.networks[0].channels = vec!["#invalid".into()];
+
+        for data in &["[modules".to_string(), to_toml(&invalid), to_toml(&removed)] {
+            std::fs::write(&path, data).unwrap();
+            assert!(reload(&states).await.is_err());
+            assert_eq!(channels(&*states[0].lock().await), vec!["#new"]);
+        }
+
+        drop(states);
+        let mut sent = vec![];
+        for (follow, rx) in follows {
+            follow.await.unwrap().unwrap();
+            sent.push(rx.collect::<Vec<_>>().await);
+        }
+        assert_eq!(sent[0], vec!["JOIN #new\r\n", "PART #old\r\n"]);
+        assert!(sent[1].is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_templates_file() {
+        let path = std::env::temp_dir().join(format!("noye-watch-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = crate::TemplateStore::new(crate::DEFAULT_TEMPLATES, &path).unwrap();
+        let resolver = crate::resolver::new(store);
+        let lag = || async {
+            let lag = responses::Lag::Lag { lag: "5ms".into() };
+            resolve_template(resolver.clone(), None, lag).await.unwrap()
+        };
+        assert_eq!(lag().await, "lag: 5ms");
+
+        let mut watched = Watched::new(path.clone()).await;
+        assert!(!watched.changed().await);
+
+        std::fs::write(&path, "[lag]\nlag = 'the lag is ${lag}'").unwrap();
+        assert!(watched.changed().await);
+        assert!(!watched.changed().await);
+        reload_templates(&resolver).await;
+        assert_eq!(lag().await, "the lag is 5ms");
+
+        // a broken file keeps the old templates
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, "[lag").unwrap();
+        reload_templates(&resolver).await;
+        assert_eq!(lag().await, "the lag is 5ms");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(watched.changed().await);
+        reload_templates(&resolver).await;
+        assert_eq!(lag().await, "lag: 5ms");
+    }
+
+    #[tokio::test]
+    async fn report_error() {
+        let path = std::env::temp_dir().join(format!("noye-report-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = crate::TemplateStore::new(crate::DEFAULT_TEMPLATES, &path).unwrap();
+
+        let mut config = Config::default();
+        config.irc_config.permissions.owner = vec!["museun!~m@localhost".into()];
+
+        let mut tracker = Tracker::default();
+        for line in &[
+            ":noye!~n@localhost JOIN #test\r\n",
+            ":museun!~m@localhost JOIN #test\r\n",
+            ":someone!~s@localhost JOIN #test\r\n",
+        ] {
+            let msg = crate::irc::RawMessage::parse(line).unwrap();
+            let event = crate::irc::Event::from_raw(&msg).unwrap();
+            tracker.apply("noye", msg.prefix.as_ref(), &msg.tags, &event);
+        }
+
+        let mut state = State::default();
+        state.insert(CachedConfig::new(config, "noye.toml"));
+        state.insert(tracker);
+        state.insert(crate::resolver::new(store));
+
+        // the writer is full, so this has to wait for the runner
+        let (mut tx, mut rx) = tokio::sync::mpsc::channel(1);
+        tx.send("PING :full\r\n".to_string()).await.unwrap();
+
+        let state = Arc::new(Mutex::new(state));
+        let reporting = tokio::spawn({
+            let state = state.clone();
+            async move {
+                let err = anyhow::anyhow!("invalid staleness").context("cannot load noye.toml");
+                report(&state, &mut Writer(tx), &err).await
+            }
+        });
+
+        // which needs the state to drain it
+        tokio::time::pause();
+        tokio::time::delay_for(Duration::from_secs(1)).await;
+        assert!(state.try_lock().is_ok());
+        assert_eq!(rx.recv().await.unwrap(), "PING :full\r\n");
+        reporting.await.unwrap().unwrap();
+
+        assert_eq!(
+            rx.collect::<Vec<_>>().await,
+            vec![
+                "NOTICE museun :cannot reload the config, keeping the old one: \
+                 cannot load noye.toml: invalid staleness\r\n"
+            ]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
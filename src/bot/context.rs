@@ -1,13 +1,19 @@
-use super::{Message, Responder, State, Writer};
+use super::{
+    args::Parsed,
+    permissions::{Identity, Role},
+    tracker, Cooldown, Cooldowns, Event, Message, ModuleFilter, OwnNick, Responder, Shutdown,
+    State, Tracker, Writer,
+};
 
 use std::sync::Arc;
-use tokio::sync::{Mutex, Notify};
+use tokio::sync::Mutex;
 
 #[derive(Clone)]
 pub struct ContextArgs {
-    pub quit: Arc<Notify>,
+    pub quit: Shutdown,
     pub writer: Writer,
     pub state: Arc<Mutex<State>>,
+    pub network: Option<Arc<str>>,
 }
 
 #[derive(Clone)]
@@ -15,7 +21,13 @@ pub struct Context<A: std::fmt::Debug = Message> {
     pub args: Arc<A>,
     pub writer: Writer,
     pub state: Arc<Mutex<State>>,
-    pub quit: Arc<Notify>,
+    pub quit: Shutdown,
+    /// The command arguments, if the command has an `Args` spec
+    pub arguments: Arc<Parsed>,
+    /// The command and its arguments, without the prefix that triggered it
+    pub invocation: Option<Arc<str>>,
+    /// The network this came from, this is `None` for the unnamed network
+    pub network: Option<Arc<str>>,
 }
 
 impl<A: std::fmt::Debug> std::fmt::Debug for Context<A> {
@@ -31,74 +43,223 @@ impl<A: std::fmt::Debug> Context<A> {
             writer: ctx_args.writer,
             state: ctx_args.state,
             quit: ctx_args.quit,
+            arguments: Default::default(),
+            invocation: None,
+            network: ctx_args.network,
+        }
+    }
+
+    /// Whether `module` is enabled in `room`
+    pub async fn is_module_enabled_in(&self, room: &str, module: &str) -> anyhow::Result<bool> {
+        let state = self.state.lock().await;
+        let enabled = state.config()?.modules.is_enabled(room, module);
+        let overridden = match state.get::<ModuleFilter>() {
+            Some(filter) => filter.get(&self.key_for(room), module)?,
+            None => None,
+        };
+        Ok(overridden.unwrap_or(enabled))
+    }
+
+    /// Like `room_key`, but for any room on our network
+    pub fn key_for(&self, room: &str) -> String {
+        match self.network.as_deref() {
+            Some(network) => format!("{}/{}", network, room),
+            None => room.to_string(),
         }
     }
 }
 
+impl Context<Event> {
+    /// Whether `nick` is us
+    pub async fn is_own_nick(&self, nick: &str) -> bool {
+        let state = self.state.lock().await;
+        match state.get::<OwnNick>() {
+            Some(OwnNick(own)) => !own.is_empty() && own.eq_ignore_ascii_case(nick),
+            None => false,
+        }
+    }
+
+    /// A context for where the event happened, so the `Responder` can be used
+    ///
+    /// This is the channel if there is one, otherwise it is sent to the nick
+    pub fn as_message(&self) -> Option<Context> {
+        let nick = self.args.nick();
+        let channel = self.args.channel().or(nick)?;
+        let msg = Message {
+            sender: nick.unwrap_or_default().to_string(),
+            hostmask: None,
+            channel: channel.to_string(),
+            data: String::new(),
+            tags: Default::default(),
+        };
+        Some(Context {
+            args: Arc::new(msg),
+            writer: self.writer.clone(),
+            state: self.state.clone(),
+            quit: self.quit.clone(),
+            arguments: Default::default(),
+            invocation: None,
+            network: self.network.clone(),
+        })
+    }
+}
+
 impl Context<Message> {
     pub fn parts(&self) -> impl Iterator<Item = &'_ str> + '_ {
         self.args.data.split(' ')
     }
 
     pub fn command(&self) -> Option<&str> {
-        if self.args.data.starts_with('!') {
-            return self.args.data.split_terminator(' ').next().map(|s| &s[1..]);
-        }
-        None
+        self.invocation.as_deref()?.split_terminator(' ').next()
     }
 
     pub fn command_args(&self) -> Vec<&str> {
-        if self.args.data.starts_with('!') {
-            return self.args.data[1..].split(' ').skip(1).collect();
+        match self.invocation.as_deref() {
+            Some(invocation) => invocation.split(' ').skip(1).collect(),
+            None => vec![],
         }
-        vec![]
     }
 
     pub fn without_command(&self) -> Option<&str> {
-        if self.args.data.starts_with('!') {
-            let pos = self.args.data.find(' ')?;
-            return self.args.data.get(pos + 1..);
-        }
-        None
+        let invocation = self.invocation.as_deref()?;
+        let pos = invocation.find(' ')?;
+        invocation.get(pos + 1..)
     }
 
     pub fn nick(&self) -> &str {
         &self.args.sender
     }
 
+    /// The channel the message was sent to, or the sender for a query
     pub fn room(&self) -> &str {
-        &self.args.channel
+        self.args.reply_target()
+    }
+
+    pub fn network(&self) -> Option<&str> {
+        self.network.as_deref()
+    }
+
+    /// The room, qualified by the network so the same room on different networks is kept apart
+    ///
+    /// This is just the room for the unnamed network, so data stored before there were networks
+    /// is kept
+    pub fn room_key(&self) -> String {
+        self.key_for(self.room())
+    }
+
+    /// Whether the message was sent directly to us
+    pub fn is_query(&self) -> bool {
+        self.args.is_query()
+    }
+
+    /// The channel this message was sent to, as the tracker sees it
+    pub async fn channel(&self) -> Option<tracker::Channel> {
+        let state = self.state.lock().await;
+        state.get::<Tracker>()?.channel(self.room()).cloned()
+    }
+
+    /// The sender's `nick!user@host`, if it is known
+    pub async fn hostmask(&self) -> Option<String> {
+        let state = self.state.lock().await;
+        state.get::<Tracker>()?.user(self.nick())?.hostmask()
+    }
+
+    /// Whether the sender is an operator in the channel
+    pub async fn is_op(&self) -> bool {
+        self.channel()
+            .await
+            .and_then(|channel| channel.member(self.nick()).map(|m| m.is_op()))
+            .unwrap_or_default()
     }
 
     pub async fn config(&self) -> anyhow::Result<crate::Config> {
-        self.state.lock().await.config().await.map(Clone::clone)
+        self.state.lock().await.config().map(Clone::clone)
+    }
+
+    /// The highest role the sender has
+    pub async fn role(&self) -> anyhow::Result<Option<Role>> {
+        let state = self.state.lock().await;
+        // prefer the account from the message, otherwise fallback to what the tracker knows
+        let account = match self.args.tags.account() {
+            Some(account) => Some(account.to_string()),
+            None => state
+                .get::<Tracker>()
+                .and_then(|tracker| tracker.user(self.nick()))
+                .and_then(|user| user.account.clone()),
+        };
+
+        let identity = Identity {
+            hostmask: self.args.hostmask.as_deref(),
+            account: account.as_deref(),
+        };
+        let permissions = state.config()?.irc_config.permissions();
+        Ok(identity.role(&permissions))
     }
 
-    pub async fn expect_owner<R: ?Sized + Responder>(
+    /// Replies to the sender if they don't have at least `role`
+    pub async fn expect_role<R: Responder>(
         &self,
+        role: Role,
         responder: &mut R,
     ) -> anyhow::Result<()> {
-        if self
-            .state
-            .lock()
-            .await
-            .config()
-            .await?
-            .irc_config
-            .owners
-            .contains(&self.args.sender)
-        {
+        if self.role().await?.filter(|&has| has >= role).is_some() {
             return Ok(());
         }
 
-        responder
-            .reply(self.clone(), crate::responses::Builtin::NotOwner)
-            .await?;
+        let resp = match role {
+            Role::Owner => crate::responses::Builtin::NotOwner,
+            role => crate::responses::Builtin::NotAllowed {
+                role: role.to_string(),
+            },
+        };
+        responder.reply(self.clone(), resp).await?;
 
         // This is needed to signal that this is an error
         crate::util::dont_care()
     }
 
+    /// Checks the cooldown for `command`, replying if the sender was throttled and the cooldown wants that
+    ///
+    /// A cooldown in the config replaces the `default`
+    pub async fn expect_cooldown<R: Responder>(
+        &self,
+        command: &str,
+        default: Option<&Cooldown>,
+        responder: &mut R,
+    ) -> anyhow::Result<()> {
+        let remaining = {
+            let mut state = self.state.lock().await;
+            let cooldown = match state.config()?.cooldowns.get(command) {
+                Some(config) => Cooldown::from_config(config)?,
+                None => match default {
+                    Some(cooldown) => cooldown.clone(),
+                    None => return Ok(()),
+                },
+            };
+
+            let cooldowns = state.expect_get_mut::<Cooldowns>()?;
+            match cooldowns.check(command, &cooldown, self.room(), self.nick()) {
+                Ok(..) => return Ok(()),
+                Err(remaining) if cooldown.reply => remaining,
+                Err(..) => return crate::util::dont_care(),
+            }
+        };
+
+        let remaining = format!("{}s", remaining.as_secs().max(1));
+        responder
+            .reply(
+                self.clone(),
+                crate::responses::Builtin::Cooldown { remaining },
+            )
+            .await?;
+        crate::util::dont_care()
+    }
+
+    /// Whether `module` is enabled in the channel this message came from
+    pub async fn is_module_enabled(&self, module: &str) -> anyhow::Result<bool> {
+        self.is_module_enabled_in(self.room(), module).await
+    }
+
     pub fn get_links(&self) -> anyhow::Result<Vec<url::Url>> {
         self.get_links_filter(|_| true)
     }
@@ -124,11 +285,10 @@ impl Context<Message> {
             .state
             .lock()
             .await
-            .config()
-            .await?
+            .config()?
             .irc_config
             .channels
-            .contains(&self.args.sender)
+            .contains(&self.args.channel)
         {
             return Ok(());
         }
@@ -0,0 +1,173 @@
+use crate::config;
+
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+/// How often a command can be used
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Cooldown {
+    pub global: Option<Duration>,
+    pub channel: Option<Duration>,
+    pub user: Option<Duration>,
+    /// Tell the user when they've been throttled
+    pub reply: bool,
+}
+
+impl Cooldown {
+    pub fn global(mut self, period: Duration) -> Self {
+        self.global.replace(period);
+        self
+    }
+
+    pub fn channel(mut self, period: Duration) -> Self {
+        self.channel.replace(period);
+        self
+    }
+
+    pub fn user(mut self, period: Duration) -> Self {
+        self.user.replace(period);
+        self
+    }
+
+    pub fn reply(mut self, reply: bool) -> Self {
+        self.reply = reply;
+        self
+    }
+
+    pub fn from_config(config: &config::Cooldown) -> anyhow::Result<Self> {
+        let parse = |s: &Option<String>| -> anyhow::Result<Option<Duration>> {
+            match s {
+                Some(s) => Ok(Some(Duration::from_secs(
+                    simple_duration_parse::parse_secs(s)?,
+                ))),
+                None => Ok(None),
+            }
+        };
+
+        Ok(Self {
+            global: parse(&config.global)?,
+            channel: parse(&config.channel)?,
+            user: parse(&config.user)?,
+            reply: config.reply,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Global,
+    Channel(String),
+    User(String),
+}
+
+/// When things were last used, keyed by a name and a `Scope`
+#[derive(Default, Debug, Clone)]
+pub struct Cooldowns(HashMap<(String, Scope), Instant>);
+
+impl Cooldowns {
+    /// How long ago `name` was last triggered in `scope`
+    pub fn elapsed(&self, name: &str, scope: &Scope) -> Option<Duration> {
+        self.0
+            .get(&(name.to_string(), scope.clone()))
+            .map(Instant::elapsed)
+    }
+
+    pub fn trigger(&mut self, name: &str, scope: Scope) {
+        self.0.insert((name.to_string(), scope), Instant::now());
+    }
+
+    /// Checks each scope of the cooldown. If none are active, they are all triggered
+    ///
+    /// Otherwise this returns the longest time left
+    pub fn check(
+        &mut self,
+        name: &str,
+        cooldown: &Cooldown,
+        channel: &str,
+        user: &str,
+    ) -> Result<(), Duration> {
+        let scopes = [
+            (Scope::Global, cooldown.global),
+            (
+                Scope::Channel(channel.to_ascii_lowercase()),
+                cooldown.channel,
+            ),
+            (Scope::User(user.to_ascii_lowercase()), cooldown.user),
+        ];
+
+        let remaining = scopes
+            .iter()
+            .filter_map(|(scope, period)| {
+                let elapsed = self.elapsed(name, scope)?;
+                period.and_then(|period| period.checked_sub(elapsed))
+            })
+            .filter(|left| *left > Duration::from_secs(0))
+            .max();
+
+        if let Some(remaining) = remaining {
+            return Err(remaining);
+        }
+
+        for (scope, period) in scopes.iter() {
+            if period.is_some() {
+                self.trigger(name, scope.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn scopes() {
+        tokio::time::pause();
+
+        let cooldown = Cooldown::default()
+            .user(Duration::from_secs(30))
+            .channel(Duration::from_secs(10));
+
+        let mut cooldowns = Cooldowns::default();
+        assert!(cooldowns.check("hp", &cooldown, "#test", "museun").is_ok());
+        assert_eq!(
+            cooldowns.check("hp", &cooldown, "#test", "other"),
+            Err(Duration::from_secs(10))
+        );
+        assert_eq!(
+            cooldowns.check("hp", &cooldown, "#other", "MUSEUN"),
+            Err(Duration::from_secs(30))
+        );
+        assert!(cooldowns.check("hp", &cooldown, "#other", "other").is_ok());
+        assert!(cooldowns
+            .check("pictures", &cooldown, "#test", "museun")
+            .is_ok());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(cooldowns.check("hp", &cooldown, "#test", "another").is_ok());
+        assert_eq!(
+            cooldowns.check("hp", &cooldown, "#test", "museun"),
+            Err(Duration::from_secs(20))
+        );
+
+        tokio::time::advance(Duration::from_secs(20)).await;
+        assert!(cooldowns.check("hp", &cooldown, "#test", "museun").is_ok());
+    }
+
+    #[test]
+    fn from_config() {
+        let cooldown = Cooldown::from_config(&config::Cooldown {
+            user: Some("1m".into()),
+            reply: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            cooldown,
+            Cooldown::default()
+                .user(Duration::from_secs(60))
+                .reply(true)
+        );
+    }
+}
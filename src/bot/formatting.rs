@@ -0,0 +1,166 @@
+//! mIRC formatting for templates
+//!
+//! Templates can use `{b}` bold, `{i}` italic, `{u}` underline, `{r}` reset, `{c:red}` or
+//! `{c:red,black}` for colours and `{c}` to end a colour. `{{` is a literal `{`
+const BOLD: char = '\x02';
+const COLOR: char = '\x03';
+const ITALIC: char = '\x1D';
+const UNDERLINE: char = '\x1F';
+const STRIKETHROUGH: char = '\x1E';
+const MONOSPACE: char = '\x11';
+const REVERSE: char = '\x16';
+const RESET: char = '\x0F';
+
+const CODES: &[char] = &[
+    BOLD,
+    COLOR,
+    ITALIC,
+    UNDERLINE,
+    STRIKETHROUGH,
+    MONOSPACE,
+    REVERSE,
+    RESET,
+];
+
+const COLORS: &[&str] = &[
+    "white",
+    "black",
+    "blue",
+    "green",
+    "red",
+    "brown",
+    "purple",
+    "orange",
+    "yellow",
+    "light_green",
+    "cyan",
+    "light_cyan",
+    "light_blue",
+    "pink",
+    "grey",
+    "light_grey",
+];
+
+/// Replaces the formatting tags in `template` with their control codes
+///
+/// `${name}` placeholders are left alone
+pub fn render(template: &str) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let placeholder = rest[..start].ends_with('$');
+        let after = &rest[start + 1..];
+
+        if placeholder || after.starts_with('{') {
+            out.push('{');
+            rest = if placeholder { after } else { &after[1..] };
+            continue;
+        }
+
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("unterminated formatting tag"))?;
+        tag(&after[..end], &mut out)?;
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn tag(tag: &str, out: &mut String) -> anyhow::Result<()> {
+    let code = match tag {
+        "b" => BOLD,
+        "i" => ITALIC,
+        "u" => UNDERLINE,
+        "r" => RESET,
+        "c" => COLOR,
+        tag if tag.starts_with("c:") => {
+            let mut parts = tag[2..].splitn(2, ',');
+            let fg = color(parts.next().unwrap_or_default())?;
+            out.push(COLOR);
+            out.push_str(&format!("{:02}", fg));
+            if let Some(bg) = parts.next() {
+                out.push_str(&format!(",{:02}", color(bg)?));
+            }
+            return Ok(());
+        }
+        tag => anyhow::bail!("unknown formatting tag '{{{}}}'", tag),
+    };
+    out.push(code);
+    Ok(())
+}
+
+fn color(name: &str) -> anyhow::Result<usize> {
+    let name = name.trim();
+    COLORS
+        .iter()
+        .position(|color| color.eq_ignore_ascii_case(name))
+        .or_else(|| name.parse().ok().filter(|&n: &usize| n < COLORS.len()))
+        .ok_or_else(|| anyhow::anyhow!("unknown colour '{}'", name))
+}
+
+/// Removes all of the formatting from `data`, for channels that don't allow it (e.g. `+c`)
+pub fn strip(data: &str) -> String {
+    fn skip_digits(s: &str) -> &str {
+        let n = s.bytes().take(2).take_while(u8::is_ascii_digit).count();
+        &s[n..]
+    }
+
+    let mut out = String::with_capacity(data.len());
+    let mut rest = data;
+    while let Some(pos) = rest.find(CODES) {
+        out.push_str(&rest[..pos]);
+        let code = rest[pos..].chars().next();
+        // all of the codes are a single byte
+        rest = &rest[pos + 1..];
+        if code == Some(COLOR) {
+            rest = skip_digits(rest);
+            if rest.starts_with(',') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                rest = skip_digits(&rest[1..]);
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_tags() {
+        let tests = vec![
+            ("{b}${title}{b} | ${views}", "\x02${title}\x02 | ${views}"),
+            ("{i}{u}text{r}", "\x1D\x1Ftext\x0F"),
+            ("{c:red}5{c} views", "\x03045\x03 views"),
+            ("{c:light_blue,black}text", "\x0312,01text"),
+            ("{c:4}text", "\x0304text"),
+            ("{{b}", "{b}"),
+            ("no tags, ${count} times", "no tags, ${count} times"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(render(input).unwrap(), expected, "{}", input);
+        }
+
+        for input in &["{bold}", "{c:rainbow}", "{c:16}", "{b", "{c:red,}"] {
+            assert!(render(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn strip_codes() {
+        let tests = vec![
+            ("\x02${title}\x02 | ${views}", "${title} | ${views}"),
+            ("\x03045\x03 views", "5 views"),
+            ("\x0312,01text\x0F", "text"),
+            ("\x034,text", ",text"),
+            ("\x1D\x1F\x1E\x11\x16plain", "plain"),
+            ("plain", "plain"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(strip(input), expected, "{:?}", input);
+        }
+    }
+}
@@ -1,30 +1,99 @@
-use super::{Context, Responder};
+use super::{Args, CommandIndex, Context, Cooldown, Event, EventKind, Message, Responder, Role};
 use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
 
 pub type AnyhowFut<'a> = Pin<Box<dyn Future<Output = anyhow::Result<()>> + 'a + Send>>;
 
-pub trait Handler<R: Send + 'static>: Send + 'static {
+/// Something that can be called with a `Context`, commands and passives get a `Message` and event
+/// handlers get the `Event`
+pub trait Handler<R: Send + 'static, A: std::fmt::Debug = Message>: Send + 'static {
     type Fut: Future<Output = anyhow::Result<()>> + Send + 'static;
-    fn call(&self, state: Context, responder: R) -> Self::Fut;
+    fn call(&self, state: Context<A>, responder: R) -> Self::Fut;
 }
 
-impl<F, Fut, R> Handler<R> for F
+impl<F, Fut, R, A> Handler<R, A> for F
 where
-    F: Fn(Context, R) -> Fut + Send + 'static,
+    F: Fn(Context<A>, R) -> Fut + Send + 'static,
     Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
     R: Responder + Send + 'static,
+    A: std::fmt::Debug,
 {
     type Fut = AnyhowFut<'static>;
-    fn call(&self, state: Context, responder: R) -> Self::Fut {
+    fn call(&self, state: Context<A>, responder: R) -> Self::Fut {
         Box::pin((self)(state, responder))
     }
 }
 
+pub struct CommandEntry<R> {
+    pub(super) handler: Arc<dyn Handler<R, Fut = AnyhowFut<'static>> + Send + 'static>,
+    pub(super) role: Option<Role>,
+    pub(super) cooldown: Option<Cooldown>,
+    pub(super) module: Option<String>,
+    pub(super) args: Option<Args>,
+    pub(super) query: bool,
+}
+
+impl<R> CommandEntry<R> {
+    /// Only allow users with at least this role to use the command
+    pub fn role(&mut self, role: Role) -> &mut Self {
+        self.role.replace(role);
+        self
+    }
+
+    /// The default cooldown for the command, this can be replaced in the config
+    pub fn cooldown(&mut self, cooldown: Cooldown) -> &mut Self {
+        self.cooldown.replace(cooldown);
+        self
+    }
+
+    /// The arguments the command expects, these are checked before the handler is called
+    ///
+    /// Commands without these are given their arguments as-is
+    pub fn args(&mut self, args: Args) -> &mut Self {
+        self.args.replace(args);
+        self
+    }
+
+    /// Allow the command to be used in a private message
+    pub fn query(&mut self) -> &mut Self {
+        self.query = true;
+        self
+    }
+
+    /// The module this command belongs to, so it can be disabled per channel
+    pub fn module(&mut self, module: impl ToString) -> &mut Self {
+        self.module.replace(module.to_string());
+        self
+    }
+}
+
 pub struct CommandsMap<R> {
-    pub(super) map: HashMap<String, Arc<dyn Handler<R, Fut = AnyhowFut<'static>> + Send + 'static>>,
+    pub(super) map: HashMap<String, CommandEntry<R>>,
     _marker: std::marker::PhantomData<R>,
 }
 
+// these are cloned for each network, so they can't require `R: Clone` like a derive would
+impl<R> Clone for CommandEntry<R> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            role: self.role,
+            cooldown: self.cooldown.clone(),
+            module: self.module.clone(),
+            args: self.args.clone(),
+            query: self.query,
+        }
+    }
+}
+
+impl<R> Clone for CommandsMap<R> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            _marker: std::marker::PhantomData::default(),
+        }
+    }
+}
+
 impl<R> Default for CommandsMap<R> {
     fn default() -> Self {
         Self {
@@ -35,7 +104,11 @@ impl<R> Default for CommandsMap<R> {
 }
 
 impl<R: Responder + Send + 'static> CommandsMap<R> {
-    pub fn add<H, F>(&mut self, cmd: impl ToString, handler: H) -> anyhow::Result<()>
+    pub fn add<H, F>(
+        &mut self,
+        cmd: impl ToString,
+        handler: H,
+    ) -> anyhow::Result<&mut CommandEntry<R>>
     where
         H: Handler<R, Fut = F>,
         F: Future<Output = anyhow::Result<()>> + Send + 'static,
@@ -46,18 +119,50 @@ impl<R: Responder + Send + 'static> CommandsMap<R> {
             anyhow::bail!("{} already exists as a command", cmd)
         }
 
-        self.map
-            .insert(cmd, Arc::new(move |state, resp| handler.call(state, resp)));
+        let entry = CommandEntry {
+            handler: Arc::new(move |state, resp| handler.call(state, resp)),
+            role: None,
+            cooldown: None,
+            module: None,
+            args: None,
+            query: false,
+        };
+        Ok(self.map.entry(cmd).or_insert(entry))
+    }
 
-        Ok(())
+    /// An index of the commands, for `!help`
+    pub fn index(&self) -> CommandIndex {
+        let mut index = CommandIndex::default();
+        for (name, cmd) in &self.map {
+            index.insert(name, cmd.role, cmd.args.clone().unwrap_or_default());
+        }
+        index
+    }
+
+    /// The modules the commands belong to
+    pub fn modules(&self) -> impl Iterator<Item = &str> + '_ {
+        self.map.values().filter_map(|cmd| cmd.module.as_deref())
     }
 }
 
 pub struct PassivesList<R> {
-    pub(super) list: Vec<Arc<dyn Handler<R, Fut = AnyhowFut<'static>> + Send + 'static>>,
+    /// The passives, with the module they belong to
+    pub(super) list: Vec<(
+        String,
+        Arc<dyn Handler<R, Fut = AnyhowFut<'static>> + Send + 'static>,
+    )>,
     _marker: std::marker::PhantomData<R>,
 }
 
+impl<R> Clone for PassivesList<R> {
+    fn clone(&self) -> Self {
+        Self {
+            list: self.list.clone(),
+            _marker: std::marker::PhantomData::default(),
+        }
+    }
+}
+
 impl<R> Default for PassivesList<R> {
     fn default() -> Self {
         Self {
@@ -68,13 +173,74 @@ impl<R> Default for PassivesList<R> {
 }
 
 impl<R: Responder + Send + 'static> PassivesList<R> {
-    pub fn add<H, F>(&mut self, handler: H)
+    /// Adds a passive for `module`, this won't be called in channels where the module is disabled
+    pub fn add<H, F>(&mut self, module: impl ToString, handler: H)
     where
         H: Handler<R, Fut = F>,
         F: Future<Output = anyhow::Result<()>> + Send + 'static,
         F::Output: Send + 'static,
     {
-        self.list
-            .push(Arc::new(move |state, resp| handler.call(state, resp)))
+        self.list.push((
+            module.to_string(),
+            Arc::new(move |state, resp| handler.call(state, resp)),
+        ))
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &str> + '_ {
+        self.list.iter().map(|(module, _)| module.as_str())
+    }
+}
+
+type EventHandler<R> = Arc<dyn Handler<R, Event, Fut = AnyhowFut<'static>> + Send + Sync + 'static>;
+
+/// The handlers for each kind of event, with the module they belong to
+pub struct EventsMap<R> {
+    pub(super) map: HashMap<EventKind, Vec<(String, EventHandler<R>)>>,
+    _marker: std::marker::PhantomData<R>,
+}
+
+impl<R> Clone for EventsMap<R> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            _marker: std::marker::PhantomData::default(),
+        }
+    }
+}
+
+impl<R> Default for EventsMap<R> {
+    fn default() -> Self {
+        Self {
+            map: Default::default(),
+            _marker: std::marker::PhantomData::default(),
+        }
+    }
+}
+
+impl<R: Responder + Send + 'static> EventsMap<R> {
+    /// Adds a handler for `kind` events for `module`
+    ///
+    /// This won't be called for events in channels where the module is disabled
+    pub fn add<H, F>(&mut self, kind: EventKind, module: impl ToString, handler: H)
+    where
+        H: Handler<R, Event, Fut = F> + Sync,
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.map.entry(kind).or_default().push((
+            module.to_string(),
+            Arc::new(move |state, resp| handler.call(state, resp)),
+        ))
+    }
+
+    pub(super) fn get(&self, kind: EventKind) -> &[(String, EventHandler<R>)] {
+        self.map.get(&kind).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &str> + '_ {
+        self.map
+            .values()
+            .flatten()
+            .map(|(module, _)| module.as_str())
     }
 }
@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+/// The round-trip time of our own PINGs to the server
+#[derive(Clone, Default)]
+pub struct Latency(Arc<Mutex<Inner>>);
+
+#[derive(Default)]
+struct Inner {
+    pending: Option<(String, Instant)>,
+    last: Option<Duration>,
+}
+
+impl Latency {
+    /// The last measured round-trip
+    pub fn last(&self) -> Option<Duration> {
+        self.0.lock().unwrap().last
+    }
+
+    /// When the outstanding PING was sent, if there is one
+    pub fn pending_since(&self) -> Option<Instant> {
+        self.0
+            .lock()
+            .unwrap()
+            .pending
+            .as_ref()
+            .map(|&(_, sent)| sent)
+    }
+
+    pub fn ping(&self, token: impl ToString) {
+        self.0.lock().unwrap().pending = Some((token.to_string(), Instant::now()));
+    }
+
+    pub fn pong(&self, token: &str) -> Option<Duration> {
+        let mut inner = self.0.lock().unwrap();
+        match inner.pending.take() {
+            Some((expected, sent)) if expected == token => {
+                let lag = sent.elapsed();
+                inner.last.replace(lag);
+                Some(lag)
+            }
+            pending => {
+                inner.pending = pending;
+                None
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().pending.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lag() {
+        tokio::time::pause();
+
+        let latency = Latency::default();
+        assert!(latency.last().is_none());
+        assert!(latency.pending_since().is_none());
+
+        latency.ping("noye-1");
+        assert!(latency.pending_since().is_some());
+        tokio::time::advance(Duration::from_millis(250)).await;
+
+        assert!(latency.pong("noye-0").is_none());
+        assert!(latency.pending_since().is_some());
+
+        assert_eq!(latency.pong("noye-1"), Some(Duration::from_millis(250)));
+        assert_eq!(latency.last(), Some(Duration::from_millis(250)));
+        assert!(latency.pending_since().is_none());
+    }
+}
@@ -1,22 +1,73 @@
-pub use crate::irc::Message;
+pub use crate::irc::{Event, EventKind, Message};
+
+mod aliases;
+pub use aliases::Aliases;
+
+pub mod args;
+pub use args::{Args, CommandIndex, Kind};
+
+pub mod auth;
+pub use auth::{AuthStatus, Authenticator};
+
+mod capabilities;
+pub use capabilities::Capabilities;
+
+pub mod config_watcher;
+
+pub mod cooldown;
+pub use cooldown::{Cooldown, Cooldowns};
 
 mod context;
 pub use context::Context;
 
+pub mod permissions;
+pub use permissions::Role;
+
+mod latency;
+pub use latency::Latency;
+
+mod module_filter;
+pub use module_filter::ModuleFilter;
+
+pub mod registry;
+pub use registry::Registry;
+
 pub mod resolver;
 pub use resolver::Resolver;
 
+mod ready;
+pub use ready::Ready;
+
 mod responder;
 pub use responder::{Responder, WriterResponder};
 
+pub mod scheduler;
+pub use scheduler::{JobContext, Scheduler};
+
+mod shutdown;
+pub use shutdown::{Shutdown, Stop, Tasks};
+
 mod state;
-pub use state::State;
+pub use state::{JoinedChannels, OwnNick, OwnPrefix, State};
+
+pub mod tracker;
+pub use tracker::Tracker;
+
+pub mod trigger;
+
+mod templates;
+pub use templates::TemplateStore;
 
 mod writer;
 pub use writer::Writer;
 
+pub mod formatting;
+
 mod handler;
-pub use handler::{AnyhowFut, CommandsMap, Handler, PassivesList};
+pub use handler::{AnyhowFut, CommandsMap, EventsMap, Handler, PassivesList};
 
 mod runner;
 pub use runner::Runner;
+// so `TestEnv` dispatches like the runner does
+#[cfg(test)]
+pub(crate) use runner::{check_command, find_invocation};
@@ -0,0 +1,115 @@
+use crate::db::Table;
+use std::collections::BTreeSet;
+
+table!(ModuleFilterTable => "./sql/schema.sql");
+
+/// The modules that can be turned on or off in a channel
+///
+/// Changes made at runtime (with `!module`) take priority over `config::Modules::channels`. These
+/// are kept in the database by room key, so they are shared by every clone of the filter and
+/// survive a restart
+#[derive(Default, Debug, Clone)]
+pub struct ModuleFilter {
+    known: BTreeSet<String>,
+}
+
+impl ModuleFilter {
+    pub fn new<I, S>(known: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        Self {
+            known: known.into_iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    pub fn known(&self) -> impl Iterator<Item = &str> + '_ {
+        self.known.iter().map(String::as_str)
+    }
+
+    pub fn is_known(&self, module: &str) -> bool {
+        self.known.contains(module)
+    }
+
+    /// Enables or disables `module` in the room, see `Context::room_key`
+    pub fn set(&self, room: &str, module: &str, enabled: bool) -> anyhow::Result<()> {
+        let conn = crate::db::get::<ModuleFilterTable>();
+        conn.execute_named(
+            "INSERT OR REPLACE INTO module_overrides (room, module, enabled) \
+             VALUES (:room, :module, :enabled)",
+            rusqlite::named_params! {
+                ":room": room.to_ascii_lowercase(),
+                ":module": module,
+                ":enabled": enabled,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Whether `module` was enabled or disabled at runtime in the room
+    pub fn get(&self, room: &str, module: &str) -> anyhow::Result<Option<bool>> {
+        let conn = crate::db::get::<ModuleFilterTable>();
+        let mut stmt = conn.prepare(
+            "SELECT enabled FROM module_overrides WHERE room = :room AND module = :module",
+        )?;
+        let mut iter = stmt.query_map_named(
+            rusqlite::named_params! {
+                ":room": room.to_ascii_lowercase(),
+                ":module": module,
+            },
+            |row| row.get("enabled"),
+        )?;
+        Ok(iter.next().transpose()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ChannelModules, Modules};
+
+    #[test]
+    fn overrides() {
+        let _db = crate::db::get_connection();
+
+        let mut modules = Modules::default();
+        modules.channels.insert(
+            "#quiet".into(),
+            ChannelModules {
+                allow: Some(vec!["youtube".into()]),
+                ..Default::default()
+            },
+        );
+        modules.channels.insert(
+            "#Test".into(),
+            ChannelModules {
+                deny: vec!["pictures".into()],
+                ..Default::default()
+            },
+        );
+
+        assert!(modules.is_enabled("#test", "youtube"));
+        assert!(!modules.is_enabled("#test", "pictures"));
+        assert!(modules.is_enabled("#quiet", "youtube"));
+        assert!(!modules.is_enabled("#quiet", "repost"));
+        assert!(modules.is_enabled("#other", "pictures"));
+
+        let filter = ModuleFilter::new(vec!["pictures", "repost", "youtube"]);
+        assert!(filter.is_known("repost"));
+        assert!(!filter.is_known("builtin"));
+
+        filter.set("#TEST", "pictures", true).unwrap();
+        filter.set("#quiet", "youtube", false).unwrap();
+        filter.set("other/#quiet", "youtube", true).unwrap();
+        assert_eq!(filter.get("#test", "pictures").unwrap(), Some(true));
+        assert_eq!(filter.get("#quiet", "youtube").unwrap(), Some(false));
+        assert_eq!(filter.get("#quiet", "repost").unwrap(), None);
+        assert_eq!(filter.get("other/#quiet", "youtube").unwrap(), Some(true));
+
+        // every network's filter sees the changes, as does the next run
+        let other = ModuleFilter::new(vec!["youtube"]);
+        other.set("#quiet", "youtube", true).unwrap();
+        assert_eq!(filter.get("#quiet", "youtube").unwrap(), Some(true));
+    }
+}
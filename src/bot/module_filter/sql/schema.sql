@@ -0,0 +1,7 @@
+-- modules turned on or off in a room with `!module`
+CREATE TABLE IF NOT EXISTS module_overrides (
+    `room` TEXT NOT NULL,
+    `module` TEXT NOT NULL,
+    `enabled` BOOLEAN NOT NULL,
+    UNIQUE(room, module)
+)
@@ -0,0 +1,213 @@
+use crate::config::Permissions;
+
+/// The roles a command can require, higher roles include the lower ones
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Trusted,
+    Admin,
+    Owner,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Trusted => write!(f, "trusted"),
+            Self::Admin => write!(f, "admin"),
+            Self::Owner => write!(f, "owner"),
+        }
+    }
+}
+
+/// Who sent a message, as far as we know
+#[derive(Debug, Default, Clone)]
+pub struct Identity<'a> {
+    /// The `nick!user@host` of the sender
+    pub hostmask: Option<&'a str>,
+    /// The IRCv3 account the sender is logged in to
+    pub account: Option<&'a str>,
+}
+
+impl<'a> Identity<'a> {
+    /// The highest role this identity has
+    pub fn role(&self, permissions: &Permissions) -> Option<Role> {
+        let Permissions {
+            owner,
+            admin,
+            trusted,
+        } = permissions;
+
+        [
+            (Role::Owner, owner),
+            (Role::Admin, admin),
+            (Role::Trusted, trusted),
+        ]
+        .iter()
+        .find(|(_, masks)| masks.iter().any(|mask| self.matches(mask)))
+        .map(|&(role, _)| role)
+    }
+
+    /// Whether this identity matches the permission `mask`
+    ///
+    /// A mask is one of:
+    /// * `nick!user@host`, which can use `*` and `?` as wildcards
+    /// * `account:name` or `$a:name`, for the IRCv3 account name
+    /// * `q:name`, for a QuakeNet Q account (this relies on the user having their host hidden)
+    ///
+    /// Anything else is treated as an account name, a bare nickname never matches
+    pub fn matches(&self, mask: &str) -> bool {
+        let (kind, name) = match mask.find(':') {
+            Some(pos) => (&mask[..pos], &mask[pos + 1..]),
+            None if mask.contains('!') || mask.contains('@') => {
+                return self.hostmask.filter(|host| glob(mask, host)).is_some();
+            }
+            None => ("account", mask),
+        };
+
+        match kind {
+            "account" | "$a" => self
+                .account
+                .filter(|account| account.eq_ignore_ascii_case(name))
+                .is_some(),
+            "q" => {
+                let host = format!("{}.users.quakenet.org", name);
+                self.hostmask
+                    .and_then(|mask| mask.rsplit('@').next())
+                    .filter(|h| h.eq_ignore_ascii_case(&host))
+                    .is_some()
+            }
+            kind => {
+                log::warn!("unknown permission mask kind: {}", kind);
+                false
+            }
+        }
+    }
+}
+
+/// Matches `input` against `pattern`, with `*` and `?` wildcards. This ignores ASCII case
+fn glob(pattern: &str, input: &str) -> bool {
+    let (pattern, input) = (pattern.as_bytes(), input.as_bytes());
+    let (mut p, mut i) = (0, 0);
+    // the position of the last `*` and the input position it was tried at
+    let mut star = None;
+
+    while i < input.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, i));
+                p += 1;
+            }
+            Some(&ch) if ch == b'?' || ch.eq_ignore_ascii_case(&input[i]) => {
+                p += 1;
+                i += 1;
+            }
+            _ => match star {
+                Some((sp, si)) => {
+                    star = Some((sp, si + 1));
+                    p = sp + 1;
+                    i = si + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&ch| ch == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match() {
+        assert!(glob("*!*@localhost", "museun!~m@localhost"));
+        assert!(glob("MUSEUN!*@*", "museun!~m@localhost"));
+        assert!(glob("m?seun!*", "museun!~m@localhost"));
+        assert!(glob("*", ""));
+        assert!(!glob("*!*@example.com", "museun!~m@localhost"));
+        assert!(!glob("museun", "museun!~m@localhost"));
+    }
+
+    #[test]
+    fn masks() {
+        let who = Identity {
+            hostmask: Some("museun!~m@museun.users.quakenet.org"),
+            account: Some("museun"),
+        };
+        assert!(who.matches("*!*@*.users.quakenet.org"));
+        assert!(who.matches("account:MUSEUN"));
+        assert!(who.matches("museun"));
+        assert!(who.matches("q:museun"));
+        assert!(!who.matches("q:other"));
+        assert!(!who.matches("account:other"));
+
+        // someone using the nick without being logged in
+        let who = Identity {
+            hostmask: Some("museun!~m@evil.example.com"),
+            account: None,
+        };
+        assert!(!who.matches("museun"));
+        assert!(!who.matches("q:museun"));
+        assert!(who.matches("museun!*@*"));
+    }
+
+    #[test]
+    fn roles() {
+        let permissions = Permissions {
+            owner: vec!["account:museun".into()],
+            admin: vec!["*!*@admin.example.com".into()],
+            trusted: vec!["*!*@*.example.com".into()],
+        };
+
+        let role = |hostmask, account| {
+            Identity {
+                hostmask: Some(hostmask),
+                account,
+            }
+            .role(&permissions)
+        };
+
+        assert_eq!(role("m!m@localhost", Some("museun")), Some(Role::Owner));
+        assert_eq!(role("a!a@admin.example.com", None), Some(Role::Admin));
+        assert_eq!(role("t!t@host.example.com", None), Some(Role::Trusted));
+        assert_eq!(role("u!u@localhost", None), None);
+
+        assert!(Role::Owner > Role::Admin);
+        assert!(Role::Admin > Role::Trusted);
+    }
+
+    #[test]
+    fn legacy_owners() {
+        let irc = crate::config::Irc {
+            owners: vec!["museun!~m@localhost".into(), "$a:other".into()],
+            ..Default::default()
+        };
+        let permissions = irc.permissions();
+        assert_eq!(permissions.owner, vec!["museun!~m@localhost", "$a:other"]);
+
+        let who = |hostmask, account| Identity {
+            hostmask: Some(hostmask),
+            account,
+        };
+        assert_eq!(
+            who("museun!~m@localhost", None).role(&permissions),
+            Some(Role::Owner)
+        );
+        assert_eq!(
+            who("o!o@elsewhere", Some("other")).role(&permissions),
+            Some(Role::Owner)
+        );
+        // someone else using the nick
+        assert_eq!(who("museun!~x@elsewhere", None).role(&permissions), None);
+
+        // a bare nick isn't allowed
+        let mut config = crate::Config::default();
+        config.modules.repost.staleness = "1d".into();
+        config.modules.pictures.cooldown = "1m".into();
+        config.modules.pictures.quiet_time = "1h".into();
+        config.irc_config = irc;
+        config.validate().unwrap();
+        config.irc_config.owners.push("museun".into());
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, watch, Notify};
+
+/// Whether the network's connection can be used, that is we've registered and joined our channels
+///
+/// Anything queued for a connection is dropped when it is lost, so jobs wait for this and check
+/// that what they queued was sent
+#[derive(Clone)]
+pub struct Ready {
+    tx: Arc<watch::Sender<Option<u64>>>,
+    rx: watch::Receiver<Option<u64>>,
+    inner: Arc<Mutex<Inner>>,
+    requested: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct Inner {
+    connection: u64,
+    waiting: Vec<oneshot::Sender<()>>,
+}
+
+impl Default for Ready {
+    fn default() -> Self {
+        let (tx, rx) = watch::channel(None);
+        Self {
+            tx: Arc::new(tx),
+            rx,
+            inner: Default::default(),
+            requested: Default::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Ready {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Ready").field(&*self.rx.borrow()).finish()
+    }
+}
+
+impl Ready {
+    /// Marks the current connection as ready
+    pub fn set(&self) {
+        let connection = self.inner.lock().unwrap().connection;
+        // we hold a receiver, so this can't fail
+        let _ = self.tx.broadcast(Some(connection));
+    }
+
+    /// Marks the current connection as gone, anything waiting on it is told its lines weren't sent
+    pub fn reset(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.connection += 1;
+        inner.waiting.clear();
+        let _ = self.tx.broadcast(None);
+    }
+
+    /// The connection, if it is ready
+    pub fn current(&self) -> Option<u64> {
+        *self.rx.borrow()
+    }
+
+    /// Waits until the connection is ready, returning which one it is
+    pub async fn wait(&self) -> u64 {
+        let mut rx = self.rx.clone();
+        loop {
+            if let Some(connection) = *rx.borrow() {
+                return connection;
+            }
+            if rx.recv().await.is_none() {
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Waits until everything queued so far has been sent on `connection`
+    ///
+    /// This is false if the connection was lost first
+    pub async fn sent(&self, connection: u64) -> bool {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.connection != connection || self.current().is_none() {
+                return false;
+            }
+            inner.waiting.push(tx);
+        }
+        self.requested.notify();
+        rx.await.is_ok()
+    }
+
+    /// Waits until something is waiting for the queued lines to be sent
+    pub(crate) async fn requested(&self) {
+        self.requested.notified().await
+    }
+
+    /// Takes what is waiting, these should be told once everything queued before now was sent
+    pub(crate) fn take_waiting(&self) -> Vec<oneshot::Sender<()>> {
+        std::mem::take(&mut self.inner.lock().unwrap().waiting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::prelude::*;
+
+    #[tokio::test]
+    async fn ready() {
+        let ready = Ready::default();
+        assert!(ready.current().is_none());
+        assert!(ready.wait().now_or_never().is_none());
+        assert!(!ready.sent(0).await);
+
+        ready.set();
+        let connection = ready.wait().await;
+        assert_eq!(ready.current(), Some(connection));
+
+        let sent = tokio::spawn({
+            let ready = ready.clone();
+            async move { ready.sent(connection).await }
+        });
+        ready.requested().await;
+        for waiting in ready.take_waiting() {
+            waiting.send(()).unwrap();
+        }
+        assert!(sent.await.unwrap());
+
+        // the lines queued for a lost connection are dropped
+        let sent = tokio::spawn({
+            let ready = ready.clone();
+            async move { ready.sent(connection).await }
+        });
+        ready.requested().await;
+        ready.reset();
+        assert!(!sent.await.unwrap());
+        assert!(ready.current().is_none());
+
+        ready.set();
+        assert_ne!(ready.wait().await, connection);
+        assert!(!ready.sent(connection).await);
+    }
+}
@@ -0,0 +1,322 @@
+use serde::de::{
+    self, value::Error, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, VariantAccess,
+    Visitor,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use template::{NameCasing::Original, Template};
+
+/// Every response variant, so templates can be checked before they are used
+#[derive(Default, Clone, Debug)]
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    /// Adds each variant of `T`
+    ///
+    /// The variants and their fields are found through `Deserialize`, so `T` has to be a plain
+    /// enum of unit and struct variants, with fields that can be strings or numbers
+    pub fn with<T>(mut self) -> anyhow::Result<Self>
+    where
+        T: Template + Send + Sync + 'static,
+        for<'de> T: Deserialize<'de>,
+    {
+        for variant in 0.. {
+            let mut found = Found::default();
+            let result = T::deserialize(Reflect {
+                variant,
+                found: &mut found,
+            });
+            if variant >= found.variants.len() {
+                break;
+            }
+
+            let sample = result
+                .map_err(|err| anyhow::anyhow!("cannot register {}: {}", T::name(Original), err))?;
+            self.entries.push(Entry {
+                namespace: T::namespace(Default::default()),
+                variant: sample.variant(Default::default()),
+                fields: found.fields,
+                apply: Arc::new(move |data| sample.apply(data).is_some()),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, ns: &str, var: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.namespace == ns && entry.variant == var)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> + '_ {
+        self.entries.iter()
+    }
+}
+
+/// A single response variant
+#[derive(Clone)]
+pub struct Entry {
+    namespace: &'static str,
+    variant: &'static str,
+    fields: &'static [&'static str],
+    apply: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+impl std::fmt::Debug for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Entry")
+            .field("namespace", &self.namespace)
+            .field("variant", &self.variant)
+            .field("fields", &self.fields)
+            .finish()
+    }
+}
+
+impl Entry {
+    pub fn namespace(&self) -> &str {
+        self.namespace
+    }
+
+    pub fn variant(&self) -> &str {
+        self.variant
+    }
+
+    pub fn fields(&self) -> &[&str] {
+        self.fields
+    }
+
+    /// Checks the placeholders in `data` against the fields of this variant
+    pub fn check(&self, data: &str) -> Vec<String> {
+        let mut problems = vec![];
+        let mut rest = data;
+        while let Some(start) = rest.find("${") {
+            if rest[..start].contains('}') {
+                problems.push("unmatched '}'".to_string());
+            }
+
+            let after = &rest[start + 2..];
+            let end = match after.find('}') {
+                Some(end) => end,
+                None => {
+                    problems.push("unterminated '${'".to_string());
+                    return problems;
+                }
+            };
+
+            let name = &after[..end];
+            if !self.fields.contains(&name) {
+                problems.push(format!(
+                    "unknown field '{}', expected one of: {}",
+                    name,
+                    self.fields.join(", ")
+                ));
+            }
+            rest = &after[end + 1..];
+        }
+
+        if rest.contains('}') {
+            problems.push("unmatched '}'".to_string());
+        }
+        if problems.is_empty() && !(self.apply)(data) {
+            problems.push("cannot be applied".to_string());
+        }
+        problems
+    }
+}
+
+#[derive(Default)]
+struct Found {
+    variants: &'static [&'static str],
+    fields: &'static [&'static str],
+}
+
+/// Deserializes the `variant`th variant of an enum, recording its fields
+struct Reflect<'a> {
+    variant: usize,
+    found: &'a mut Found,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Reflect<'a> {
+    type Error = Error;
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.found.variants = variants;
+        if self.variant >= variants.len() {
+            return Err(de::Error::custom("no more variants"));
+        }
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("only enums can be registered"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+impl<'de, 'a> EnumAccess<'de> for Reflect<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let name = self.found.variants[self.variant];
+        let value = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(name))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Reflect<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, _seed: T) -> Result<T::Value, Error> {
+        Err(de::Error::custom("newtype variants aren't supported"))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("tuple variants aren't supported"))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.found.fields = fields;
+        visitor.visit_map(Fields { fields })
+    }
+}
+
+/// Gives each field a placeholder value
+struct Fields {
+    fields: &'static [&'static str],
+}
+
+impl<'de> MapAccess<'de> for Fields {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.fields.first() {
+            Some(field) => seed
+                .deserialize(IntoDeserializer::<Error>::into_deserializer(*field))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        self.fields = &self.fields[1..];
+        seed.deserialize(Placeholder)
+    }
+}
+
+/// An empty string, or zero
+struct Placeholder;
+
+macro_rules! zero {
+    ($($func:ident)*) => {
+        $(
+            fn $func<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.visit_u64(0)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Placeholder {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_none()
+    }
+
+    zero! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i128 u128 char str string bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responses;
+
+    #[test]
+    fn reflect() {
+        let registry = Registry::default()
+            .with::<responses::Lag>()
+            .and_then(|r| r.with::<responses::Vimeo>())
+            .unwrap();
+
+        let variants = registry
+            .entries()
+            .map(|entry| (entry.namespace(), entry.variant()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            variants,
+            vec![("lag", "lag"), ("lag", "unknown"), ("vimeo", "video")]
+        );
+
+        assert!(registry.get("lag", "unknown").unwrap().fields().is_empty());
+        assert_eq!(
+            registry.get("vimeo", "video").unwrap().fields(),
+            &["id", "width", "height", "duration", "fps", "title", "owner"]
+        );
+    }
+
+    #[test]
+    fn check() {
+        let registry = Registry::default().with::<responses::Lag>().unwrap();
+        let entry = registry.get("lag", "lag").unwrap();
+
+        assert!(entry.check("lag: ${lag}").is_empty());
+        assert!(entry.check("no lag").is_empty());
+        assert_eq!(entry.check("${lag}}"), vec!["unmatched '}'"]);
+        assert_eq!(entry.check("${lag}} ${lag}"), vec!["unmatched '}'"]);
+        assert_eq!(entry.check("lag: ${lag"), vec!["unterminated '${'"]);
+        assert_eq!(
+            entry.check("${lagg}"),
+            vec!["unknown field 'lagg', expected one of: lag"]
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        #[derive(Template, Deserialize)]
+        #[namespace("flags")]
+        #[allow(dead_code)]
+        enum Flags {
+            Flag { on: bool },
+        }
+
+        let err = Registry::default().with::<Flags>().unwrap_err();
+        assert!(err.to_string().starts_with("cannot register Flags:"));
+    }
+}
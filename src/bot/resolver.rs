@@ -1,13 +1,9 @@
-use anyhow::Context as _;
+use super::TemplateStore;
 use std::sync::Arc;
-use template::TemplateStore;
 use tokio::sync::Mutex;
 
-pub type Resolver = Arc<Mutex<template::Resolver<Box<dyn TemplateStore + Send>>>>;
+pub type Resolver = Arc<Mutex<TemplateStore>>;
 
-pub fn new<S: TemplateStore + Send + 'static>(store: S) -> anyhow::Result<Resolver> {
-    template::Resolver::new(Box::new(store) as Box<dyn TemplateStore + Send>)
-        .with_context(|| "cannot create template resolver")
-        .map(Mutex::new)
-        .map(Arc::new)
+pub fn new(store: TemplateStore) -> Resolver {
+    Arc::new(Mutex::new(store))
 }
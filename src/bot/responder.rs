@@ -1,5 +1,7 @@
-use super::{AnyhowFut, Context, Message, Resolver};
+use super::{formatting, AnyhowFut, Context, OwnPrefix, Resolver};
+use crate::irc::{split, MAX_LINE};
 
+use anyhow::Context as _;
 use serde::{Deserialize, Serialize};
 use template::{NameCasing::Original, Template};
 use tokio::sync::mpsc;
@@ -45,11 +47,8 @@ impl Responder for WriterResponder {
         let mut writer = self.writer.clone();
 
         Box::pin(async move {
-            let resp = resolve_template(resolver, template).await?;
-            writer
-                .send(format!("PRIVMSG {} :{}\r\n", &context.args.channel, resp))
-                .await?;
-            Ok(())
+            let resp = render(resolver, &context, template).await?;
+            send_lines(&mut writer, &context, context.room(), &resp).await
         })
     }
 
@@ -69,20 +68,82 @@ impl Responder for WriterResponder {
         let mut writer = self.writer.clone();
 
         Box::pin(async move {
-            let resp = resolve_template(resolver, template).await?;
-            let Message {
-                channel, sender, ..
-            } = &*context.args;
-
-            writer
-                .send(format!("PRIVMSG {} :{}: {}\r\n", &channel, &sender, resp))
-                .await?;
-            Ok(())
+            let resp = render(resolver, &context, template).await?;
+            // there's no one else to address in a query
+            if context.is_query() {
+                return send_lines(&mut writer, &context, context.room(), &resp).await;
+            }
+
+            let resp = format!("{}: {}", context.nick(), resp);
+            send_lines(&mut writer, &context, context.room(), &resp).await
         })
     }
 }
 
-pub async fn resolve_template<T>(resolver: Resolver, template: T) -> anyhow::Result<String>
+/// Resolves the template for the room, removing the formatting if the room doesn't allow it
+async fn render<T>(resolver: Resolver, context: &Context, template: T) -> anyhow::Result<String>
+where
+    T: Template + Send,
+{
+    let strip = context
+        .config()
+        .await?
+        .irc_config
+        .formatting
+        .strip(context.room());
+
+    let resp = resolve_template(resolver, Some(context.room()), template).await?;
+    if strip {
+        return Ok(formatting::strip(&resp));
+    }
+    Ok(resp)
+}
+
+/// Sends `data` to `target`, split so each line fits once the server has prepended our prefix
+async fn send_lines(
+    writer: &mut mpsc::Sender<String>,
+    context: &Context,
+    target: &str,
+    data: &str,
+) -> anyhow::Result<()> {
+    // if we haven't seen our prefix yet, assume the longest one we're likely to get
+    const UNKNOWN_PREFIX: usize = 30 + 1 + 10 + 1 + 63;
+
+    let (prefix, max_lines) = {
+        let state = context.state.lock().await;
+        let max_lines = state.config()?.irc_config.max_lines;
+        let prefix = state
+            .get::<OwnPrefix>()
+            .map(|prefix| prefix.0.len())
+            .filter(|&len| len > 0)
+            .unwrap_or(UNKNOWN_PREFIX);
+        (prefix, max_lines)
+    };
+
+    // :prefix PRIVMSG target :data\r\n
+    let overhead = 1 + prefix + " PRIVMSG ".len() + target.len() + " :".len() + "\r\n".len();
+    let mut lines = split(data, MAX_LINE.saturating_sub(overhead).max(1));
+    if let Some(max) = max_lines {
+        if lines.len() > max {
+            log::debug!("dropping {} lines for {}", lines.len() - max, target);
+            lines.truncate(max.max(1));
+        }
+    }
+
+    for line in lines {
+        writer
+            .send(format!("PRIVMSG {} :{}\r\n", target, line))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Resolves the template, using the overrides for `channel` if there are any
+pub async fn resolve_template<T>(
+    resolver: Resolver,
+    channel: Option<&str>,
+    template: T,
+) -> anyhow::Result<String>
 where
     T: Template + Send,
 {
@@ -95,11 +156,14 @@ where
     resolver
         .lock()
         .await
-        .resolve(ns, var)
+        .get(channel, ns, var)
         .ok_or_else(|| anyhow::anyhow!("cannot resolve template for: {}: {}->{}", name, ns, var))
         .and_then(|data| {
+            // the tags are replaced first so the values can't add any formatting
+            let data = formatting::render(data)
+                .with_context(|| format!("invalid template for: {}: {}->{}", name, ns, var))?;
             template
-                .apply(data)
+                .apply(&data)
                 .ok_or_else(|| anyhow::anyhow!("invalid template for: {}: {}->{}", name, ns, var))
         })
 }
@@ -1,123 +1,278 @@
+use super::auth::{self, AuthStatus, Authenticator};
 use super::*;
-use crate::irc::{Command, Prefix, RawMessage};
+use crate::{
+    config::{Auth, CachedConfig},
+    irc::{numeric::*, Command, Event, Prefix, RawMessage},
+};
 
 use std::sync::Arc;
-use tokio::sync::{Mutex, Notify};
+use tokio::sync::Mutex;
 
 pub struct Runner<R> {
-    pub quit: Arc<Notify>,
+    pub quit: Shutdown,
+    /// The handlers that are running, so we can wait for them before quitting
+    pub tasks: Tasks,
+    pub nick: String,
+    pub latency: Latency,
+    /// Whether the connection is ready for the scheduled jobs
+    pub ready: Ready,
     pub writer: Writer,
     pub commands: CommandsMap<R>,
     pub passives: PassivesList<R>,
+    pub events: EventsMap<R>,
     pub state: Arc<Mutex<State>>,
+    /// The network this is connected to, `None` for the unnamed network
+    pub network: Option<Arc<str>>,
     _phantom: std::marker::PhantomData<R>,
 }
 
 impl<R: Responder + Send + 'static> Runner<R> {
     pub fn new(
-        state: State,
+        mut state: State,
         writer: Writer,
         commands: CommandsMap<R>,
         passives: PassivesList<R>,
+        events: EventsMap<R>,
     ) -> Self {
-        let (quit, _phantom) = Default::default();
+        let (tasks, nick, _phantom) = Default::default();
+        let quit = state.get::<Shutdown>().cloned().unwrap_or_default();
+        let (latency, ready) = (Latency::default(), Ready::default());
+        state.insert(Capabilities::default());
+        state.insert(Authenticator::default());
+        state.insert(JoinedChannels::default());
+        state.insert(OwnNick::default());
+        state.insert(OwnPrefix::default());
+        state.insert(Tracker::default());
+        state.insert(latency.clone());
+        state.insert(ready.clone());
+        let network = state
+            .get::<CachedConfig>()
+            .and_then(|config| config.network())
+            .map(Into::into);
         Self {
             quit,
+            tasks,
+            nick,
+            latency,
+            ready,
             commands,
             passives,
+            events,
             writer,
             state: Arc::new(Mutex::new(state)),
+            network,
             _phantom,
         }
     }
 
     pub async fn handle(&mut self, data: &str, responder: R) -> anyhow::Result<()> {
         let msg = RawMessage::parse(data)?;
+        self.track(&msg).await?;
 
+        // the handlers see the state after we've reacted to the message
+        let event = Event::from_raw(&msg).ok();
+        self.react(msg, responder.clone()).await?;
+        if let Some(event) = event {
+            self.dispatch_event(event, responder);
+        }
+        Ok(())
+    }
+
+    async fn react(&mut self, msg: RawMessage, responder: R) -> anyhow::Result<()> {
         match msg.command {
             Command::Privmsg => {
-                if msg.data.is_none() {
-                    log::warn!("message was missing 'data': {:?}", msg);
-                    return Ok(());
+                // with echo-message we'll see our own messages
+                if let Some(Prefix::User { nick, .. }) = &msg.prefix {
+                    if *nick == self.nick {
+                        return Ok(());
+                    }
                 }
 
-                let context = Context::new(
-                    msg.into_message(),
-                    context::ContextArgs {
-                        quit: self.quit.clone(),
-                        writer: self.writer.clone(),
-                        state: self.state.clone(),
-                    },
-                );
+                let msg = match msg.into_message() {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        log::warn!("invalid message: {}", err);
+                        return Ok(());
+                    }
+                };
+
+                let invocation = find_invocation(&*self.state.lock().await, &msg, &self.nick)?;
+
+                let mut context = Context::new(msg, self.context_args());
+                context.invocation = invocation;
                 self.dispatch(context, responder.clone())
             }
 
-            Command::Ping => {
-                // TODO add in netsplit detection (incremental ping backoff)
-                self.writer
-                    .raw(format!("PONG {}", msg.data.unwrap()))
-                    .await?;
-            }
+            Command::Ping | Command::Pong => match Event::from_raw(&msg)? {
+                Event::Ping { token } => self.writer.raw(format!("PONG :{}", token)).await?,
+                Event::Pong { token } => {
+                    if let Some(lag) = self.latency.pong(&token) {
+                        log::trace!("lag: {}ms", lag.as_millis());
+                    }
+                }
+                event => log::warn!("expected a ping or a pong, got: {:?}", event),
+            },
+
+            Command::Cap => self.negotiate(msg).await?,
 
             Command::Ready => {
+                self.nick = msg
+                    .param(0)
+                    .ok_or_else(|| anyhow::anyhow!("RPL_WELCOME did not have our nick"))?
+                    .to_string();
                 let mut state = self.state.lock().await;
-                let crate::config::Irc {
-                    q_name,
-                    q_pass,
-                    channels,
-                    ..
-                } = &state.config().await?.irc_config;
-
-                // TODO if we don't get a response from Q within N seconds, just join the channels
-                match (q_name, q_pass) {
-                    (Some(q_name), Some(q_pass)) if !q_name.is_empty() && !q_pass.is_empty() => {
-                        log::info!("authing with Q");
-                        self.writer
-                            .raw(format!(
-                                "PRIVMSG Q@CServe.quakenet.org :AUTH {} {}",
-                                q_name, q_pass
-                            ))
-                            .await?;
-                        self.writer.raw(format!("MODE {} +x", &msg.args[0])).await?;
+                state.insert(OwnNick(self.nick.clone()));
+                let (method, timeout) = {
+                    let irc = &state.config()?.irc_config;
+                    (irc.auth(), irc.auth_timeout.clone())
+                };
+
+                let auth = state.expect_get_mut::<Authenticator>()?;
+                let join = match method {
+                    Auth::None => {
+                        log::warn!("not authenticating, joining channels");
+                        auth.complete(AuthStatus::Failed);
+                        auth.register()
                     }
-                    _ => {
-                        log::warn!("not authing with Q, joining channels");
-                        for channel in channels {
-                            self.writer.join(channel).await?;
+                    // this would have happened during capability negotiation
+                    method if method.is_sasl() => {
+                        if auth.status() == AuthStatus::Pending {
+                            log::warn!("SASL was not available, joining channels");
+                            auth.complete(AuthStatus::Failed);
                         }
+                        auth.register()
+                    }
+                    method => {
+                        let (join, attempt) = (auth.register(), auth.attempt());
+                        auth::start(&method, &self.nick, &mut self.writer).await?;
+                        self.auth_timeout(attempt, &timeout);
+                        join
                     }
+                };
+
+                if join {
+                    auth::join_channels(&mut state, &mut self.writer).await?;
                 }
             }
 
             Command::Quit | Command::Nick => {
                 let mut state = self.state.lock().await;
-                let name = &state.config().await?.irc_config.name;
-                if let Some(Prefix::User { nick, .. }) = &msg.prefix {
-                    if nick == name {
+                let name = &state.config()?.irc_config.name;
+                match (Event::from_raw(&msg)?, &msg.prefix) {
+                    (Event::Nick { old, new }, Some(Prefix::User { user, host, .. }))
+                        if old == self.nick =>
+                    {
+                        log::info!("our nick changed to: {}", new);
+                        state.insert(OwnPrefix(format!("{}!{}@{}", new, user, host)));
+                        state.insert(OwnNick(new.clone()));
+                        self.nick = new;
+                    }
+                    (Event::Nick { old: nick, .. }, ..) | (Event::Quit { nick, .. }, ..)
+                        if nick == *name && nick != self.nick =>
+                    {
                         log::info!("attempting to regain our nick: {}", name);
                         self.writer.nick(name).await?;
                     }
+                    _ => {}
                 }
             }
 
             Command::NickCollision => {
-                let new_nick = format!("{}_", msg.args[1]);
+                let nick = msg
+                    .param(1)
+                    .ok_or_else(|| anyhow::anyhow!("ERR_NICKNAMEINUSE did not have a nick"))?;
+                let new_nick = format!("{}_", nick);
                 log::info!("our nickname is taken, changing to: {}", new_nick);
                 self.writer.nick(new_nick).await?;
             }
 
+            Command::Join | Command::Part | Command::Kick => {
+                let (nick, channel, joined) = match Event::from_raw(&msg)? {
+                    Event::Join { nick, channel } => (nick, channel, true),
+                    Event::Part { nick, channel, .. } | Event::Kick { nick, channel, .. } => {
+                        (nick, channel, false)
+                    }
+                    event => {
+                        log::warn!("expected a join, part or kick, got: {:?}", event);
+                        return Ok(());
+                    }
+                };
+                if nick != self.nick {
+                    return Ok(());
+                }
+
+                let mut state = self.state.lock().await;
+                if let (true, Some(Prefix::User { nick, user, host })) = (joined, &msg.prefix) {
+                    state.insert(OwnPrefix(format!("{}!{}@{}", nick, user, host)));
+                }
+
+                let channels = &mut state.expect_get_mut::<JoinedChannels>()?.0;
+                if joined {
+                    log::info!("joined {}", channel);
+                    channels.insert(channel);
+                } else {
+                    log::info!("left {}", channel);
+                    channels.remove(&channel);
+                }
+            }
+
             Command::Invite => {
-                let channel = msg.data.unwrap();
-                log::info!("we were invited to {}, joining", channel);
-                self.writer.join(channel).await?;
+                if let Event::Invite { channel, .. } = Event::from_raw(&msg)? {
+                    log::info!("we were invited to {}, joining", channel);
+                    self.writer.join(channel).await?;
+                }
+            }
+
+            Command::Error => {
+                if let Event::Error { message } = Event::from_raw(&msg)? {
+                    log::warn!("server sent an error: {}", message);
+                }
+            }
+
+            Command::Authenticate => {
+                if msg.args.get(0).map(|s| s.as_str()) == Some("+") {
+                    let method = self.state.lock().await.config()?.irc_config.auth();
+                    for line in auth::sasl_payload(&method) {
+                        self.writer.raw(line).await?;
+                    }
+                }
             }
 
-            Command::Numeric(396) => {
-                // TODO this is where the timeout should be handled for Q
-                log::info!("successfully authenticated with Q");
+            Command::Numeric(RPL_LOGGEDIN) => {
+                let account = msg.args.get(2).cloned().unwrap_or_default();
+                log::info!("logged in as: {}", account);
+
                 let mut state = self.state.lock().await;
-                for channel in &state.config().await?.irc_config.channels {
-                    self.writer.join(channel).await?;
+                let method = state.config()?.irc_config.auth();
+                let auth = state.expect_get_mut::<Authenticator>()?;
+                auth.set_account(account);
+                if let Auth::NickServ { .. } = method {
+                    auth::complete(&mut state, &mut self.writer, AuthStatus::Authenticated).await?;
+                }
+            }
+
+            Command::Numeric(RPL_SASLSUCCESS) => {
+                log::info!("SASL authentication was successful");
+                let mut state = self.state.lock().await;
+                auth::complete(&mut state, &mut self.writer, AuthStatus::Authenticated).await?;
+                self.writer.raw("CAP END").await?;
+            }
+
+            Command::Numeric(ERR_NICKLOCKED) | Command::Numeric(ERR_SASLFAIL..=ERR_SASLABORTED) => {
+                log::warn!(
+                    "SASL authentication failed: {}",
+                    msg.data.unwrap_or_default()
+                );
+                let mut state = self.state.lock().await;
+                auth::complete(&mut state, &mut self.writer, AuthStatus::Failed).await?;
+                self.writer.raw("CAP END").await?;
+            }
+
+            Command::Numeric(RPL_HOSTHIDDEN) => {
+                let mut state = self.state.lock().await;
+                if let Auth::Q { .. } = state.config()?.irc_config.auth() {
+                    log::info!("successfully authenticated with Q");
+                    auth::complete(&mut state, &mut self.writer, AuthStatus::Authenticated).await?;
                 }
             }
 
@@ -126,6 +281,155 @@ impl<R: Responder + Send + 'static> Runner<R> {
         Ok(())
     }
 
+    /// Resets the per-connection state so we can register again after reconnecting
+    pub(crate) async fn reset(&mut self) -> anyhow::Result<()> {
+        self.nick.clear();
+        self.latency.clear();
+        self.ready.reset();
+        let mut state = self.state.lock().await;
+        state.insert(Capabilities::default());
+        state.insert(OwnNick::default());
+        state.insert(OwnPrefix::default());
+        state.expect_get_mut::<Tracker>()?.clear();
+        state.expect_get_mut::<Authenticator>()?.reset();
+        Ok(())
+    }
+
+    async fn track(&self, msg: &RawMessage) -> anyhow::Result<()> {
+        let event = match Event::from_raw(msg) {
+            Ok(Event::Other) | Ok(Event::Ping { .. }) | Ok(Event::Pong { .. }) => return Ok(()),
+            Ok(event) => event,
+            Err(err) => {
+                log::debug!("cannot track: {}", err);
+                return Ok(());
+            }
+        };
+
+        let mut state = self.state.lock().await;
+        let tracker = state.expect_get_mut::<Tracker>()?;
+        tracker.apply(&self.nick, msg.prefix.as_ref(), &msg.tags, &event);
+        Ok(())
+    }
+
+    fn auth_timeout(&self, attempt: u64, timeout: &str) {
+        const DEFAULT_TIMEOUT: u64 = 15;
+
+        let timeout = simple_duration_parse::parse_secs(timeout).unwrap_or(DEFAULT_TIMEOUT);
+        let (state, mut writer) = (self.state.clone(), self.writer.clone());
+
+        tokio::spawn(async move {
+            tokio::time::delay_for(std::time::Duration::from_secs(timeout)).await;
+
+            let mut state = state.lock().await;
+            // we've reconnected since this was started
+            match state.get::<Authenticator>() {
+                Some(auth) if auth.attempt() == attempt => {}
+                _ => return,
+            }
+            if let Err(err) = auth::complete(&mut state, &mut writer, AuthStatus::Failed).await {
+                log::error!("cannot complete authentication: {}", err)
+            }
+        });
+    }
+
+    async fn negotiate(&mut self, msg: RawMessage) -> anyhow::Result<()> {
+        let list = msg.data.as_deref().unwrap_or_default();
+
+        let mut state = self.state.lock().await;
+        let (mut wanted, method) = {
+            let irc = &state.config()?.irc_config;
+            (irc.capabilities.clone(), irc.auth())
+        };
+        if method.is_sasl() {
+            wanted.push("sasl".into());
+        }
+
+        let caps = state.expect_get_mut::<Capabilities>()?;
+
+        match msg.args.get(1).map(|s| s.as_str()) {
+            Some("LS") => {
+                caps.offer(list);
+                // a '*' before the list means there are more lines coming
+                if msg.args.get(2).map(|s| s.as_str()) == Some("*") {
+                    return Ok(());
+                }
+
+                let request = caps.take_wanted(&wanted);
+                if request.is_empty() {
+                    log::info!("no capabilities to request");
+                    return self.writer.raw("CAP END").await;
+                }
+
+                log::debug!("requesting capabilities: {}", request.join(" "));
+                self.writer
+                    .raw(format!("CAP REQ :{}", request.join(" ")))
+                    .await
+            }
+            Some("ACK") => {
+                caps.acknowledge(list);
+                // we'll end the negotiation once SASL has finished
+                if let (true, Some(mechanism)) =
+                    (caps.is_enabled("sasl"), auth::sasl_mechanism(&method))
+                {
+                    log::info!("authenticating with SASL {}", mechanism);
+                    return self.writer.raw(format!("AUTHENTICATE {}", mechanism)).await;
+                }
+                self.writer.raw("CAP END").await
+            }
+            Some("NAK") => {
+                log::warn!("server rejected capabilities: {}", list);
+                self.writer.raw("CAP END").await
+            }
+            Some("NEW") => Ok(()),
+            Some("DEL") => {
+                caps.remove(list);
+                Ok(())
+            }
+            _ => {
+                log::warn!("unknown CAP message: {:?}", msg);
+                Ok(())
+            }
+        }
+    }
+
+    fn context_args(&self) -> context::ContextArgs {
+        context::ContextArgs {
+            quit: self.quit.clone(),
+            writer: self.writer.clone(),
+            state: self.state.clone(),
+            network: self.network.clone(),
+        }
+    }
+
+    fn dispatch_event(&self, event: Event, responder: R) {
+        use crate::util::inspect_err;
+        use futures::prelude::*;
+
+        let handlers = self.events.get(event.kind());
+        if handlers.is_empty() {
+            return;
+        }
+
+        let context = Context::new(event, self.context_args());
+        for (module, handler) in handlers {
+            let (context, responder) = (context.clone(), responder.clone());
+            let (module, handler) = (module.clone(), handler.clone());
+            let fut = async move {
+                let enabled = match context.args.channel() {
+                    Some(channel) => context.is_module_enabled_in(channel, &module).await?,
+                    None => true,
+                };
+                // the handler is only called for modules that are enabled here
+                if enabled {
+                    handler.call(context, responder).await?;
+                }
+                Ok(())
+            }
+            .inspect_err(move |err| inspect_err(err, || "event handler"));
+            self.tasks.spawn(fut);
+        }
+    }
+
     fn dispatch(&self, context: Context, responder: R) {
         use crate::util::inspect_err;
         use futures::prelude::*;
@@ -134,19 +438,166 @@ impl<R: Responder + Send + 'static> Runner<R> {
             self.commands
                 .map
                 .get(head)
+                // only some commands can be used in a query
+                .filter(|cmd| cmd.query || !context.is_query())
                 .map(|cmd| (cmd, head.to_string()))
         }) {
-            let fut = cmd
-                .call(context.clone(), responder.clone())
-                .inspect_err(move |err| inspect_err(err, || format!("command '{}'", head)));
-            tokio::task::spawn(fut);
+            let parsed = match &cmd.args {
+                Some(args) => args.parse(&head, &context.command_args()).map(Some),
+                None => Ok(None),
+            };
+
+            // the handler won't do anything until it is polled
+            let call = parsed.map(|parsed| {
+                let mut context = context.clone();
+                if let Some(parsed) = parsed {
+                    context.arguments = std::sync::Arc::new(parsed);
+                }
+                cmd.handler.call(context, responder.clone())
+            });
+
+            let (role, cooldown, module) = (cmd.role, cmd.cooldown.clone(), cmd.module.clone());
+            let (context, mut responder, name) = (context.clone(), responder.clone(), head.clone());
+            let fut = async move {
+                if let Some(module) = module {
+                    if !context.is_module_enabled(&module).await? {
+                        return Ok(());
+                    }
+                }
+                let call = check_command(&context, role, call, &mut responder).await?;
+                context
+                    .expect_cooldown(&name, cooldown.as_ref(), &mut responder)
+                    .await?;
+                call.await
+            }
+            .inspect_err(move |err| inspect_err(err, || format!("command '{}'", head)));
+            self.tasks.spawn(fut);
+        }
+
+        for (module, passive) in &self.passives.list {
+            let call = passive.call(context.clone(), responder.clone());
+            let (context, module) = (context.clone(), module.clone());
+            let fut = async move {
+                if context.is_module_enabled(&module).await? {
+                    call.await?;
+                }
+                Ok(())
+            }
+            .inspect_err(move |err| inspect_err(err, || "passive"));
+            self.tasks.spawn(fut);
+        }
+    }
+}
+
+/// Finds the command `msg` invoked, if any, with its alias resolved
+///
+/// `nick` is who we are, so the command can be addressed to us
+pub(crate) fn find_invocation(
+    state: &State,
+    msg: &Message,
+    nick: &str,
+) -> anyhow::Result<Option<Arc<str>>> {
+    let commands = &state.config()?.irc_config.commands;
+    let nick = Some(nick).filter(|_| commands.addressed);
+    let prefixes = commands.prefixes(&msg.channel);
+    let invocation =
+        trigger::strip(&msg.data, prefixes, nick, msg.is_query()).map(ToString::to_string);
+
+    Ok(match (invocation, state.get::<Aliases>()) {
+        (Some(invocation), Some(aliases)) => {
+            Some(aliases.resolve(&invocation).unwrap_or(invocation).into())
+        }
+        (invocation, _) => invocation.map(Into::into),
+    })
+}
+
+/// Checks the sender has the `role` for a command, then that its arguments were valid
+///
+/// If they weren't the usage is sent to the sender instead
+pub(crate) async fn check_command<R: Responder, T>(
+    context: &Context,
+    role: Option<Role>,
+    parsed: Result<T, crate::responses::Usage>,
+    responder: &mut R,
+) -> anyhow::Result<T> {
+    if let Some(role) = role {
+        context.expect_role(role, responder).await?;
+    }
+    match parsed {
+        Ok(parsed) => Ok(parsed),
+        Err(usage) => {
+            responder.reply(context.clone(), usage).await?;
+            crate::util::dont_care()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ChannelModules, test::YamlResponder, CachedConfig, Config};
+
+    #[tokio::test]
+    async fn events() {
+        let mut config = Config::default();
+        config.modules.channels.insert(
+            "#quiet".into(),
+            ChannelModules {
+                deny: vec!["greet".into()],
+                ..Default::default()
+            },
+        );
+        let mut state = State::default();
+        state.insert(CachedConfig::new(config, "noye.toml"));
+
+        let seen = Arc::new(std::sync::Mutex::new(vec![]));
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut events = EventsMap::default();
+        for kind in &[EventKind::Join, EventKind::Kick] {
+            let (seen, calls) = (seen.clone(), calls.clone());
+            events.add(*kind, "greet", move |context: Context<Event>, _| {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let seen = seen.clone();
+                async move {
+                    let room = context.as_message().map(|c| c.room().to_string());
+                    let own = context.is_own_nick(context.args.nick().unwrap()).await;
+                    seen.lock().unwrap().push((context.args.kind(), room, own));
+                    Ok(())
+                }
+            });
         }
 
-        for passive in &self.passives.list {
-            let fut = passive
-                .call(context.clone(), responder.clone())
-                .inspect_err(move |err| inspect_err(err, || "passive"));
-            tokio::task::spawn(fut);
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut runner = Runner::new(
+            state,
+            Writer(tx),
+            CommandsMap::default(),
+            PassivesList::default(),
+            events,
+        );
+
+        for line in &[
+            ":irc 001 noye :welcome\r\n",
+            ":noye!~n@localhost JOIN #test\r\n",
+            ":museun!~m@localhost JOIN #test\r\n",
+            ":museun!~m@localhost JOIN #quiet\r\n",
+            ":museun!~m@localhost PART #test\r\n",
+            ":museun!~m@localhost KICK #test noye :bye\r\n",
+        ] {
+            runner.handle(line, YamlResponder::default()).await.unwrap();
+            runner.tasks.idle().await;
         }
+
+        let test = Some("#test".to_string());
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (EventKind::Join, test.clone(), true),
+                (EventKind::Join, test.clone(), false),
+                (EventKind::Kick, test, true),
+            ]
+        );
+        // the join in #quiet never reached the handler
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
     }
 }
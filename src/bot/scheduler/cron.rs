@@ -0,0 +1,249 @@
+use anyhow::Context as _;
+use time::{Duration, OffsetDateTime};
+
+/// A cron-like schedule: `minute hour day-of-month month day-of-week`, in UTC
+///
+/// Each field can be `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a list of those
+/// separated by commas. Like cron, if both of the day fields are restricted then a day matching
+/// either of them is used. Sunday is `0` (or `7`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    spec: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let fields = spec.split_whitespace().collect::<Vec<_>>();
+        let (minutes, hours, days, months, weekdays) = match fields.as_slice() {
+            [minutes, hours, days, months, weekdays] => {
+                (*minutes, *hours, *days, *months, *weekdays)
+            }
+            _ => anyhow::bail!("'{}' should have 5 fields", spec),
+        };
+
+        let field = |input: &str, name, min, max| {
+            parse_field(input, min, max).with_context(|| format!("invalid {} in '{}'", name, spec))
+        };
+
+        let mut weekday_set = field(weekdays, "day of the week", 0, 7)?;
+        // 7 is sunday too
+        if weekday_set & (1 << 7) != 0 {
+            weekday_set = (weekday_set & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            spec: fields.join(" "),
+            minutes: field(minutes, "minute", 0, 59)?,
+            hours: field(hours, "hour", 0, 23)?,
+            days: field(days, "day of the month", 1, 31)?,
+            months: field(months, "month", 1, 12)?,
+            weekdays: weekday_set,
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    /// The first time after `time` that matches, `None` if nothing matches in the next few years
+    pub fn next_after(&self, time: OffsetDateTime) -> Option<OffsetDateTime> {
+        let time = time.to_offset(time::UtcOffset::UTC);
+        let limit = time + Duration::days(5 * 366);
+
+        let mut next = time
+            - Duration::seconds(time.second().into())
+            - Duration::nanoseconds(time.nanosecond().into())
+            + Duration::minutes(1);
+
+        while next <= limit {
+            if !self.is_day(next) {
+                next = next.date().next_day().midnight().assume_utc();
+                continue;
+            }
+            if !contains(self.hours, next.hour()) {
+                next += Duration::minutes(60 - i64::from(next.minute()));
+                continue;
+            }
+            if !contains(self.minutes, next.minute()) {
+                next += Duration::minutes(1);
+                continue;
+            }
+            return Some(next);
+        }
+        None
+    }
+
+    fn is_day(&self, time: OffsetDateTime) -> bool {
+        if !contains(self.months, time.month()) {
+            return false;
+        }
+        let day = contains(self.days, time.day());
+        let weekday = contains(self.weekdays, time.weekday().number_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+impl std::fmt::Display for Cron {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+fn contains(set: u64, value: u8) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses a field into a bitset of the values it matches
+fn parse_field(input: &str, min: u8, max: u8) -> anyhow::Result<u64> {
+    let number = |s: &str| -> anyhow::Result<u8> {
+        let n = s
+            .parse::<u8>()
+            .with_context(|| format!("'{}' isn't a number", s))?;
+        anyhow::ensure!(
+            (min..=max).contains(&n),
+            "{} isn't between {} and {}",
+            n,
+            min,
+            max
+        );
+        Ok(n)
+    };
+
+    let mut set = 0;
+    for part in input.split(',') {
+        let mut iter = part.splitn(2, '/');
+        let (range, step) = (iter.next().unwrap_or_default(), iter.next());
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.find('-') {
+                Some(pos) => (number(&range[..pos])?, number(&range[pos + 1..])?),
+                // `5/10` means starting at 5
+                None if step.is_some() => (number(range)?, max),
+                None => {
+                    let n = number(range)?;
+                    (n, n)
+                }
+            },
+        };
+        anyhow::ensure!(start <= end, "'{}' is backwards", range);
+
+        let step = match step {
+            Some(step) => step
+                .parse::<u8>()
+                .ok()
+                .filter(|&step| step > 0)
+                .with_context(|| format!("'{}' isn't a valid step", step))?,
+            None => 1,
+        };
+
+        for n in (start..=end).step_by(step.into()) {
+            set |= 1 << n;
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> OffsetDateTime {
+        OffsetDateTime::parse(date, time::Format::Rfc3339).unwrap()
+    }
+
+    fn next(spec: &str, date: &str) -> String {
+        Cron::parse(spec)
+            .unwrap()
+            .next_after(at(date))
+            .unwrap()
+            .format("%FT%H:%M:%SZ")
+    }
+
+    #[test]
+    fn parse() {
+        let cron = Cron::parse("*/15  9-17 * * 1-5").unwrap();
+        assert_eq!(cron.spec(), "*/15 9-17 * * 1-5");
+        assert_eq!(cron.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(cron.weekdays, 0b111110);
+        assert_eq!(Cron::parse("0 0 * * 7").unwrap().weekdays, 1);
+        assert_eq!(
+            Cron::parse("5,10/20 * * * *").unwrap().minutes,
+            1 << 5 | 1 << 10 | 1 << 30 | 1 << 50
+        );
+
+        for spec in &[
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "10-5 * * * *",
+            "*/0 * * * *",
+            "a * * * *",
+        ] {
+            assert!(Cron::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn next_after() {
+        assert_eq!(
+            next("* * * * *", "2020-06-01T12:30:15Z"),
+            "2020-06-01T12:31:00Z"
+        );
+        assert_eq!(
+            next("0 * * * *", "2020-06-01T12:00:00Z"),
+            "2020-06-01T13:00:00Z"
+        );
+        assert_eq!(
+            next("30 9 * * *", "2020-06-01T12:00:00Z"),
+            "2020-06-02T09:30:00Z"
+        );
+        assert_eq!(
+            next("0 0 1 * *", "2020-12-15T00:00:00Z"),
+            "2021-01-01T00:00:00Z"
+        );
+        // 2020-06-06 is a saturday
+        assert_eq!(
+            next("0 12 * * 1", "2020-06-06T00:00:00Z"),
+            "2020-06-08T12:00:00Z"
+        );
+        // either of the day fields can match
+        assert_eq!(
+            next("0 12 20 * 1", "2020-06-06T00:00:00Z"),
+            "2020-06-08T12:00:00Z"
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2021-01-01T00:00:00Z"),
+            "2024-02-29T00:00:00Z"
+        );
+        // times in other offsets are treated as UTC
+        assert_eq!(
+            next("0 12 * * *", "2020-06-01T15:00:00+02:00"),
+            "2020-06-02T12:00:00Z"
+        );
+
+        assert!(Cron::parse("0 0 31 2 *")
+            .unwrap()
+            .next_after(at("2020-01-01T00:00:00Z"))
+            .is_none());
+    }
+}
@@ -0,0 +1,653 @@
+//! Jobs that run on a timer
+//!
+//! Modules add their jobs to the `Scheduler` in `ModuleInit`. Repeating jobs run for every
+//! network, and one-shot jobs are kept in the database until they have run, so they survive a
+//! restart. Jobs only run once their network is `Ready`
+use super::{AnyhowFut, Ready, Shutdown, State, Writer};
+use crate::{db::Table, util::inspect_err};
+
+use std::{collections::HashMap, convert::TryFrom, future::Future, sync::Arc, time::Duration};
+use time::OffsetDateTime;
+use tokio::{sync::Mutex, time::Instant};
+
+mod cron;
+pub use cron::Cron;
+
+table!(JobsTable => "./sql/schema.sql");
+
+/// The longest we sleep for at once, so far off jobs notice changes to the clock
+const MAX_SLEEP: Duration = Duration::from_secs(60 * 60);
+
+type JobFn = Arc<dyn Fn(JobContext) -> AnyhowFut<'static> + Send + Sync>;
+type DueFn = Arc<dyn Fn(JobContext, String) -> AnyhowFut<'static> + Send + Sync>;
+
+/// What a job is run with, the state and connection of the network it is running for
+#[derive(Clone)]
+pub struct JobContext {
+    pub state: Arc<Mutex<State>>,
+    pub writer: Writer,
+    pub network: Option<Arc<str>>,
+}
+
+/// When a repeating job runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    fn next_after(&self, time: OffsetDateTime) -> Option<OffsetDateTime> {
+        match self {
+            Self::Every(period) => Some(time + *period),
+            Self::Cron(cron) => cron.next_after(time),
+        }
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::util::Timestamp as _;
+        match self {
+            Self::Every(period) if period.as_secs() == 0 => {
+                write!(f, "every {}ms", period.as_millis())
+            }
+            Self::Every(period) => write!(f, "every {}", period.as_readable_time()),
+            Self::Cron(cron) => write!(f, "at '{}'", cron),
+        }
+    }
+}
+
+/// A one-shot job that hasn't run yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pending {
+    pub id: i64,
+    pub network: Option<String>,
+    pub kind: String,
+    pub due: OffsetDateTime,
+    pub payload: String,
+}
+
+/// A job, for listing them
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobInfo {
+    pub name: String,
+    pub schedule: String,
+    /// When it'll run next on the network, this is `None` for repeating jobs until they've started
+    /// there
+    pub next: Option<OffsetDateTime>,
+}
+
+struct Repeating {
+    name: String,
+    schedule: Schedule,
+    job: JobFn,
+    /// When it'll run next on each network
+    next: HashMap<Option<String>, OffsetDateTime>,
+}
+
+struct Running {
+    networks: Vec<JobContext>,
+    shutdown: Shutdown,
+}
+
+#[derive(Default)]
+struct Inner {
+    repeating: Vec<Repeating>,
+    handlers: HashMap<String, DueFn>,
+    running: Option<Running>,
+}
+
+/// Runs jobs on a timer, this is shared by every network
+///
+/// Jobs are stopped when the bot shuts down, one-shot jobs that haven't run are kept for the next
+/// time
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    inner: Arc<std::sync::Mutex<Inner>>,
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("Scheduler")
+            .field(
+                "repeating",
+                &inner.repeating.iter().map(|r| &r.name).collect::<Vec<_>>(),
+            )
+            .field("handlers", &inner.handlers.keys().collect::<Vec<_>>())
+            .field("running", &inner.running.is_some())
+            .finish()
+    }
+}
+
+impl Scheduler {
+    /// Runs `job` every `period`, starting a `period` after the scheduler is started
+    pub fn every<J, F>(&self, name: impl ToString, period: Duration, job: J) -> anyhow::Result<()>
+    where
+        J: Fn(JobContext) -> F + Send + Sync + 'static,
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        anyhow::ensure!(period > Duration::from_secs(0), "the period cannot be zero");
+        self.add(name.to_string(), Schedule::Every(period), job)
+    }
+
+    /// Runs `job` whenever the cron-like `spec` matches, see `Cron` for the syntax
+    pub fn cron<J, F>(&self, name: impl ToString, spec: &str, job: J) -> anyhow::Result<()>
+    where
+        J: Fn(JobContext) -> F + Send + Sync + 'static,
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.add(name.to_string(), Schedule::Cron(Cron::parse(spec)?), job)
+    }
+
+    fn add<J, F>(&self, name: String, schedule: Schedule, job: J) -> anyhow::Result<()>
+    where
+        J: Fn(JobContext) -> F + Send + Sync + 'static,
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.repeating.iter().any(|r| r.name == name) {
+            anyhow::bail!("job '{}' already exists", name)
+        }
+
+        inner.repeating.push(Repeating {
+            name,
+            schedule,
+            job: Arc::new(move |context| Box::pin(job(context))),
+            next: HashMap::new(),
+        });
+
+        let index = inner.repeating.len() - 1;
+        if let Some(running) = &inner.running {
+            for context in &running.networks {
+                self.spawn_repeating(index, context.clone(), running.shutdown.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the one-shot jobs of `kind` when they're due, it is given their payload
+    pub fn on_due<H, F>(&self, kind: impl ToString, handler: H) -> anyhow::Result<()>
+    where
+        H: Fn(JobContext, String) -> F + Send + Sync + 'static,
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let kind = kind.to_string();
+        let mut inner = self.inner.lock().unwrap();
+        if inner.handlers.contains_key(&kind) {
+            anyhow::bail!("one-shot jobs of kind '{}' are already handled", kind)
+        }
+        inner.handlers.insert(
+            kind,
+            Arc::new(move |context, payload| Box::pin(handler(context, payload))),
+        );
+        Ok(())
+    }
+
+    /// Runs the handler for `kind` with `payload` at `due`, on `network`
+    ///
+    /// The job is saved until it has run. If `due` has passed it is run straight away
+    pub fn once(
+        &self,
+        network: Option<&str>,
+        kind: &str,
+        due: OffsetDateTime,
+        payload: impl ToString,
+    ) -> anyhow::Result<i64> {
+        let inner = self.inner.lock().unwrap();
+        if !inner.handlers.contains_key(kind) {
+            anyhow::bail!("nothing handles one-shot jobs of kind '{}'", kind)
+        }
+
+        let conn = crate::db::get::<JobsTable>();
+        conn.execute_named(
+            "INSERT INTO jobs (network, kind, due, payload) VALUES (:network, :kind, :due, :payload)",
+            rusqlite::named_params! {
+                ":network": &network,
+                ":kind": &kind,
+                ":due": unix_time(due),
+                ":payload": payload.to_string(),
+            },
+        )?;
+        let pending = Pending {
+            id: conn.last_insert_rowid(),
+            network: network.map(ToString::to_string),
+            kind: kind.to_string(),
+            due,
+            payload: payload.to_string(),
+        };
+        log::debug!("scheduled {:?}", pending);
+
+        let id = pending.id;
+        self.spawn_once(&inner, pending);
+        Ok(id)
+    }
+
+    /// The one-shot jobs that haven't run yet, the soonest first
+    pub fn pending(&self) -> anyhow::Result<Vec<Pending>> {
+        let conn = crate::db::get::<JobsTable>();
+        let mut stmt = conn.prepare("SELECT * FROM jobs ORDER BY due, id")?;
+        let pending = stmt
+            .query_map(rusqlite::NO_PARAMS, |row| {
+                Ok(Pending {
+                    id: row.get("id")?,
+                    network: row.get("network")?,
+                    kind: row.get("kind")?,
+                    due: OffsetDateTime::from_unix_timestamp(row.get("due")?),
+                    payload: row.get("payload")?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(pending)
+    }
+
+    /// The repeating jobs, then the one-shot jobs for `network`
+    pub fn jobs(&self, network: Option<&str>) -> anyhow::Result<Vec<JobInfo>> {
+        let key = network.map(ToString::to_string);
+        let mut jobs = self
+            .inner
+            .lock()
+            .unwrap()
+            .repeating
+            .iter()
+            .map(|r| JobInfo {
+                name: r.name.clone(),
+                schedule: r.schedule.to_string(),
+                next: r.next.get(&key).copied(),
+            })
+            .collect::<Vec<_>>();
+
+        jobs.extend(
+            self.pending()?
+                .into_iter()
+                .filter(|p| p.network.as_deref() == network)
+                .map(|p| JobInfo {
+                    name: p.kind,
+                    schedule: "once".into(),
+                    next: Some(p.due),
+                }),
+        );
+        Ok(jobs)
+    }
+
+    /// Starts running the jobs for each of the `networks`, until `shutdown` is stopped
+    ///
+    /// The saved one-shot jobs are loaded now, any for a network we aren't on are kept
+    pub fn start(&self, networks: Vec<JobContext>, shutdown: Shutdown) -> anyhow::Result<()> {
+        let pending = self.pending()?;
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.running.is_some() {
+            anyhow::bail!("the scheduler has already been started")
+        }
+
+        for index in 0..inner.repeating.len() {
+            for context in &networks {
+                self.spawn_repeating(index, context.clone(), shutdown.clone());
+            }
+        }
+
+        inner.running.replace(Running { networks, shutdown });
+        for pending in pending {
+            self.spawn_once(&inner, pending);
+        }
+        Ok(())
+    }
+
+    fn spawn_repeating(&self, index: usize, context: JobContext, shutdown: Shutdown) {
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            let (name, schedule, job) = {
+                let inner = inner.lock().unwrap();
+                let r = &inner.repeating[index];
+                (r.name.clone(), r.schedule.clone(), r.job.clone())
+            };
+
+            let ready = match get_ready(&context).await {
+                Ok(ready) => ready,
+                Err(err) => {
+                    inspect_err(&err, || format!("starting job '{}'", name));
+                    return;
+                }
+            };
+
+            // when the last run was due, this is timed by tokio so the tests can pause it
+            let mut last = Instant::now();
+            loop {
+                let now = OffsetDateTime::now_utc();
+                let since = Instant::now().saturating_duration_since(last);
+                // don't try to catch up if a run took too long
+                let next = match schedule.next_after(now - since) {
+                    Some(next) if next < now => schedule.next_after(now),
+                    next => next,
+                };
+                let next = match next {
+                    Some(next) => next,
+                    None => {
+                        log::warn!("job '{}' ({}) won't run again", name, schedule);
+                        break;
+                    }
+                };
+                inner.lock().unwrap().repeating[index]
+                    .next
+                    .insert(context.network.as_deref().map(ToString::to_string), next);
+
+                let due = Instant::now() + Duration::try_from(next - now).unwrap_or_default();
+                if !wait_until(next, &shutdown).await
+                    || wait_ready(&ready, &shutdown).await.is_none()
+                {
+                    break;
+                }
+
+                log::trace!("running job '{}' on {}", name, network_name(&context));
+                if let Err(err) = job(context.clone()).await {
+                    inspect_err(&err, || format!("running job '{}'", name));
+                }
+                last = due;
+            }
+        });
+    }
+
+    fn spawn_once(&self, inner: &Inner, pending: Pending) {
+        let running = match &inner.running {
+            Some(running) => running,
+            None => return,
+        };
+
+        let context = running
+            .networks
+            .iter()
+            .find(|context| context.network.as_deref() == pending.network.as_deref());
+        let context = match context {
+            Some(context) => context.clone(),
+            None => {
+                log::warn!(
+                    "keeping job {} ({}) for network '{}', we aren't on it",
+                    pending.id,
+                    pending.kind,
+                    pending.network.as_deref().unwrap_or("default")
+                );
+                return;
+            }
+        };
+
+        let handler = match inner.handlers.get(&pending.kind) {
+            Some(handler) => handler.clone(),
+            None => {
+                log::warn!(
+                    "keeping job {}, nothing handles '{}'",
+                    pending.id,
+                    pending.kind
+                );
+                return;
+            }
+        };
+
+        let shutdown = running.shutdown.clone();
+        tokio::spawn(async move {
+            let ready = match get_ready(&context).await {
+                Ok(ready) => ready,
+                Err(err) => {
+                    inspect_err(&err, || {
+                        format!("starting job {} ({})", pending.id, pending.kind)
+                    });
+                    return;
+                }
+            };
+
+            if !wait_until(pending.due, &shutdown).await {
+                return;
+            }
+
+            // it is only forgotten once what it queued has been sent
+            loop {
+                let connection = match wait_ready(&ready, &shutdown).await {
+                    Some(connection) => connection,
+                    None => return,
+                };
+
+                log::trace!("running job {} ({})", pending.id, pending.kind);
+                if let Err(err) = handler(context.clone(), pending.payload.clone()).await {
+                    inspect_err(&err, || {
+                        format!("running job {} ({}), keeping it", pending.id, pending.kind)
+                    });
+                    return;
+                }
+
+                if ready.sent(connection).await {
+                    break;
+                }
+                log::warn!(
+                    "job {} ({}) was lost with the connection, running it again",
+                    pending.id,
+                    pending.kind
+                );
+            }
+
+            let conn = crate::db::get::<JobsTable>();
+            if let Err(err) = conn.execute_named(
+                "DELETE FROM jobs WHERE id = :id",
+                rusqlite::named_params! { ":id": pending.id },
+            ) {
+                log::warn!("cannot remove job {}: {}", pending.id, err);
+            }
+        });
+    }
+}
+
+fn unix_time(time: OffsetDateTime) -> i64 {
+    (time - OffsetDateTime::unix_epoch()).whole_seconds()
+}
+
+fn network_name(context: &JobContext) -> &str {
+    context.network.as_deref().unwrap_or("default")
+}
+
+async fn get_ready(context: &JobContext) -> anyhow::Result<Ready> {
+    Ok(context.state.lock().await.expect_get::<Ready>()?.clone())
+}
+
+/// Waits until the connection is ready, returning `None` if we're shutting down first
+async fn wait_ready(ready: &Ready, shutdown: &Shutdown) -> Option<u64> {
+    tokio::select! {
+        connection = ready.wait() => Some(connection),
+        _ = shutdown.wait() => None,
+    }
+}
+
+/// Waits until `due`, returning false if we're shutting down first
+async fn wait_until(due: OffsetDateTime, shutdown: &Shutdown) -> bool {
+    loop {
+        // this is an error if it has already passed
+        let remaining = match Duration::try_from(due - OffsetDateTime::now_utc()) {
+            Ok(remaining) if remaining > Duration::from_secs(0) => remaining,
+            _ => break,
+        };
+
+        let sleep = remaining.min(MAX_SLEEP);
+        tokio::select! {
+            _ = tokio::time::delay_for(sleep) => {}
+            _ = shutdown.wait() => return false,
+        }
+        // the clock is only checked again after the long sleeps
+        if sleep == remaining {
+            break;
+        }
+    }
+    shutdown.stopping().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn network(name: Option<&str>) -> (JobContext, Ready, mpsc::Receiver<String>) {
+        let (tx, rx) = mpsc::channel(8);
+        let ready = Ready::default();
+        let mut state = State::default();
+        state.insert(ready.clone());
+        let context = JobContext {
+            state: Arc::new(Mutex::new(state)),
+            writer: Writer(tx),
+            network: name.map(Into::into),
+        };
+        (context, ready, rx)
+    }
+
+    /// Tells the jobs that their lines were sent, like the supervisor does
+    async fn sent(ready: &Ready) {
+        ready.requested().await;
+        for tx in ready.take_waiting() {
+            tx.send(()).unwrap();
+        }
+    }
+
+    async fn recv(rx: &mut mpsc::Receiver<String>) -> String {
+        tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn repeating() {
+        tokio::time::pause();
+
+        let scheduler = Scheduler::default();
+        scheduler
+            .every("tick", Duration::from_secs(60), |mut context| async move {
+                let network = network_name(&context).to_string();
+                context.writer.raw(network).await
+            })
+            .unwrap();
+        assert!(scheduler
+            .every("tick", Duration::from_secs(1), |_| async { Ok(()) })
+            .is_err());
+        assert!(scheduler
+            .every("zero", Duration::from_secs(0), |_| async { Ok(()) })
+            .is_err());
+        assert!(scheduler
+            .cron("invalid", "* *", |_| async { Ok(()) })
+            .is_err());
+        scheduler
+            .cron("daily", "0 9 * * *", |_| async { Ok(()) })
+            .unwrap();
+
+        let jobs = scheduler.jobs(None).unwrap();
+        assert_eq!(
+            jobs.iter()
+                .map(|j| (&*j.name, &*j.schedule))
+                .collect::<Vec<_>>(),
+            vec![("tick", "every 1 minute"), ("daily", "at '0 9 * * *'")]
+        );
+        assert!(jobs.iter().all(|j| j.next.is_none()));
+
+        let (a, a_ready, mut a_rx) = network(Some("a"));
+        let (b, b_ready, mut b_rx) = network(Some("b"));
+        a_ready.set();
+        let shutdown = Shutdown::default();
+        scheduler.start(vec![a, b], shutdown.clone()).unwrap();
+        assert!(scheduler.start(vec![], shutdown.clone()).is_err());
+
+        for _ in 0..2 {
+            tokio::time::advance(Duration::from_secs(59)).await;
+            assert!(a_rx.try_recv().is_err());
+            tokio::time::advance(Duration::from_secs(1)).await;
+            assert_eq!(recv(&mut a_rx).await, "a\r\n");
+        }
+
+        // this one waits until its network is ready
+        assert!(b_rx.try_recv().is_err());
+        b_ready.set();
+        assert_eq!(recv(&mut b_rx).await, "b\r\n");
+
+        // they're tracked for each network
+        for network in &[Some("a"), Some("b")] {
+            let jobs = scheduler.jobs(*network).unwrap();
+            assert!(jobs.iter().all(|j| j.next.is_some()));
+        }
+        let jobs = scheduler.jobs(None).unwrap();
+        assert!(jobs.iter().all(|j| j.next.is_none()));
+
+        shutdown.stop(None);
+        tokio::time::advance(Duration::from_secs(2 * 60)).await;
+        assert!(a_rx.try_recv().is_err());
+        assert!(b_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn one_shot() {
+        let _db = crate::db::get_connection();
+        tokio::time::pause();
+
+        let scheduler = Scheduler::default();
+        scheduler
+            .on_due("say", |mut context, payload| async move {
+                context.writer.raw(payload).await
+            })
+            .unwrap();
+        assert!(scheduler.on_due("say", |_, _| async { Ok(()) }).is_err());
+
+        let now = OffsetDateTime::now_utc();
+        let later = now + Duration::from_secs(60 * 60);
+        assert!(scheduler.once(None, "unknown", now, "").is_err());
+
+        // these are saved until the scheduler starts
+        scheduler.once(None, "say", now, "hello").unwrap();
+        let elsewhere = scheduler
+            .once(Some("other"), "say", now, "elsewhere")
+            .unwrap();
+        let jobs = scheduler.jobs(None).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].name, "say");
+        assert_eq!(jobs[0].schedule, "once");
+
+        // they wait until the network is ready
+        let (context, ready, mut rx) = network(None);
+        let shutdown = Shutdown::default();
+        scheduler.start(vec![context], shutdown.clone()).unwrap();
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(rx.try_recv().is_err());
+
+        // and they are run again if the connection is lost before what they queued was sent
+        ready.set();
+        assert_eq!(recv(&mut rx).await, "hello\r\n");
+        ready.requested().await;
+        ready.reset();
+        assert_eq!(scheduler.pending().unwrap().len(), 2);
+        ready.set();
+        assert_eq!(recv(&mut rx).await, "hello\r\n");
+        sent(&ready).await;
+
+        let soon = now + Duration::from_secs(60);
+        scheduler.once(None, "say", soon, "soon").unwrap();
+        let kept = scheduler.once(None, "say", later, "later").unwrap();
+        tokio::time::advance(Duration::from_secs(59)).await;
+        assert!(rx.try_recv().is_err());
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(recv(&mut rx).await, "soon\r\n");
+        sent(&ready).await;
+
+        // let it be removed
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let pending = scheduler.pending().unwrap();
+        assert_eq!(
+            pending.iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![elsewhere, kept]
+        );
+        assert_eq!(unix_time(pending[1].due), unix_time(later));
+        assert_eq!(pending[1].payload, "later");
+
+        // the remaining jobs survive a restart
+        shutdown.stop(None);
+        let scheduler = Scheduler::default();
+        scheduler
+            .on_due("say", |mut context, payload| async move {
+                context.writer.raw(payload).await
+            })
+            .unwrap();
+        assert_eq!(scheduler.pending().unwrap(), pending);
+    }
+}
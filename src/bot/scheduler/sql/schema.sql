@@ -0,0 +1,8 @@
+-- one-shot jobs that haven't run yet
+CREATE TABLE IF NOT EXISTS jobs (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT,
+    `network` TEXT,
+    `kind` TEXT NOT NULL,
+    `due` INTEGER NOT NULL,
+    `payload` TEXT NOT NULL
+)
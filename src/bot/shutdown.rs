@@ -0,0 +1,189 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::sync::{watch, Notify};
+
+/// Why we're quitting
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stop {
+    /// The `QUIT` message, the configured one is used if this is `None`
+    pub reason: Option<String>,
+}
+
+/// Asks the bot to quit, this is shared by every network
+///
+/// Each connection lets its running handlers finish and sends what they queued before it quits
+#[derive(Clone)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<Option<Stop>>>,
+    rx: watch::Receiver<Option<Stop>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (tx, rx) = watch::channel(None);
+        Self {
+            tx: Arc::new(tx),
+            rx,
+        }
+    }
+}
+
+impl std::fmt::Debug for Shutdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Shutdown").field(&self.stopping()).finish()
+    }
+}
+
+impl Shutdown {
+    /// Starts shutting down, only the first reason is kept
+    pub fn stop(&self, reason: Option<String>) {
+        if self.stopping().is_some() {
+            return;
+        }
+        // we hold a receiver, so this can't fail
+        let _ = self.tx.broadcast(Some(Stop { reason }));
+    }
+
+    pub fn stopping(&self) -> Option<Stop> {
+        self.rx.borrow().clone()
+    }
+
+    /// Stops on ctrl-c, or when we're asked to terminate
+    pub async fn stop_on_signal(self) {
+        #[cfg(unix)]
+        let terminate = async {
+            use tokio::signal::unix::{signal, SignalKind};
+            match signal(SignalKind::terminate()) {
+                Ok(mut terminate) => terminate.recv().await,
+                Err(err) => {
+                    log::warn!("cannot listen for SIGTERM: {}", err);
+                    futures::future::pending().await
+                }
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = futures::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => log::info!("interrupted, shutting down"),
+            _ = terminate => log::info!("terminated, shutting down"),
+        }
+        self.stop(None);
+    }
+
+    /// Waits until we're shutting down
+    pub async fn wait(&self) -> Stop {
+        let mut rx = self.rx.clone();
+        loop {
+            if let Some(stop) = rx.borrow().clone() {
+                return stop;
+            }
+            if rx.recv().await.is_none() {
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// The handlers that are still running, so they can finish before we quit
+#[derive(Clone, Default)]
+pub struct Tasks {
+    active: Arc<AtomicUsize>,
+    idle: Arc<Notify>,
+}
+
+impl std::fmt::Debug for Tasks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tasks")
+            .field("active", &self.active())
+            .finish()
+    }
+}
+
+impl Tasks {
+    pub fn spawn<F>(&self, fut: F)
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        // this is dropped even if the task panics
+        let guard = Guard(self.clone());
+        tokio::spawn(async move {
+            let _guard = guard;
+            fut.await
+        });
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Waits until there are no running tasks
+    pub async fn idle(&self) {
+        while self.active() > 0 {
+            self.idle.notified().await
+        }
+    }
+}
+
+struct Guard(Tasks);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if self.0.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::prelude::*;
+    use tokio::time::Duration;
+
+    #[tokio::test]
+    async fn stop() {
+        let shutdown = Shutdown::default();
+        assert!(shutdown.stopping().is_none());
+        assert!(shutdown.wait().now_or_never().is_none());
+
+        let waiting = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+
+        shutdown.clone().stop(Some("bye".into()));
+        shutdown.stop(Some("again".into()));
+
+        let expected = Stop {
+            reason: Some("bye".into()),
+        };
+        assert_eq!(waiting.await.unwrap(), expected);
+        assert_eq!(shutdown.stopping(), Some(expected.clone()));
+        assert_eq!(shutdown.wait().now_or_never(), Some(expected));
+    }
+
+    #[tokio::test]
+    async fn idle() {
+        let tasks = Tasks::default();
+        assert!(tasks.idle().now_or_never().is_some());
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        tasks.spawn(rx);
+        tasks.spawn(async {});
+        assert!(tasks.active() > 0);
+
+        let idle = tokio::time::timeout(Duration::from_millis(50), tasks.idle());
+        assert!(idle.await.is_err());
+
+        tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), tasks.idle())
+            .await
+            .unwrap();
+        assert_eq!(tasks.active(), 0);
+    }
+}
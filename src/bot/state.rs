@@ -2,18 +2,64 @@ use crate::{util::type_name, CachedConfig, Config};
 
 use std::{
     any::{Any, TypeId},
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
 };
 
-#[derive(Default, Debug)]
-pub struct State(HashMap<TypeId, Box<dyn Any + Send + Sync>>);
+/// The channels we're currently in, these are rejoined after a reconnect
+#[derive(Default, Debug, Clone)]
+pub struct JoinedChannels(pub BTreeSet<String>);
+
+/// The nick we registered with, this is empty until the server welcomes us
+#[derive(Default, Debug, Clone)]
+pub struct OwnNick(pub String);
+
+/// Our own `nick!user@host`, which the server prepends to everything we send
+///
+/// This is learned from our JOINs and is empty until then
+#[derive(Default, Debug, Clone)]
+pub struct OwnPrefix(pub String);
+
+/// Something in the `State`
+trait Item: Any + Send + Sync {
+    fn clone_item(&self) -> Box<dyn Item>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any + Clone + Send + Sync> Item for T {
+    fn clone_item(&self) -> Box<dyn Item> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct State(HashMap<TypeId, Box<dyn Item>>);
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("State")
+            .field("items", &self.0.len())
+            .finish()
+    }
+}
 
 impl State {
-    pub fn insert<T: 'static + Send + Sync>(&mut self, item: T) -> bool {
+    pub fn insert<T: 'static + Clone + Send + Sync>(&mut self, item: T) -> bool {
         self.0.insert(TypeId::of::<T>(), Box::new(item)).is_none()
     }
 
-    pub fn expect_insert<T: 'static + Send + Sync>(&mut self, item: T) -> anyhow::Result<()> {
+    pub fn expect_insert<T: 'static + Clone + Send + Sync>(
+        &mut self,
+        item: T,
+    ) -> anyhow::Result<()> {
         if self.0.insert(TypeId::of::<T>(), Box::new(item)).is_some() {
             anyhow::bail!("'{}' already existed in state", type_name::<T>())
         }
@@ -21,16 +67,28 @@ impl State {
         Ok(())
     }
 
+    /// A copy of this state, for another network
+    ///
+    /// Anything that is shared (e.g. behind an `Arc`) stays shared, everything else is copied
+    pub fn fork(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(&id, item)| (id, (**item).clone_item()))
+                .collect(),
+        )
+    }
+
     pub fn get<T: 'static + Send + Sync>(&self) -> Option<&T> {
         self.0
             .get(&TypeId::of::<T>())
-            .and_then(|item| item.downcast_ref::<T>())
+            .and_then(|item| (**item).as_any().downcast_ref::<T>())
     }
 
     pub fn get_mut<T: 'static + Send + Sync>(&mut self) -> Option<&mut T> {
         self.0
             .get_mut(&TypeId::of::<T>())
-            .and_then(|item| item.downcast_mut::<T>())
+            .and_then(|item| (**item).as_any_mut().downcast_mut::<T>())
     }
 
     pub fn expect_get_cloned<T: 'static + Send + Sync>(&self) -> anyhow::Result<&T> {
@@ -49,10 +107,9 @@ impl State {
             .ok_or_else(|| anyhow::anyhow!("cannot get mut: {}", type_name::<T>()))
     }
 
-    pub async fn config(&mut self) -> anyhow::Result<&Config> {
-        let config = self
-            .get_mut::<CachedConfig>()
-            .ok_or_else(|| anyhow::anyhow!("cannot get config"))?;
-        config.get().await
+    pub fn config(&self) -> anyhow::Result<&Config> {
+        self.get::<CachedConfig>()
+            .map(CachedConfig::get)
+            .ok_or_else(|| anyhow::anyhow!("cannot get config"))
     }
 }
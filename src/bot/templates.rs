@@ -0,0 +1,221 @@
+use super::{formatting, registry::Registry};
+use anyhow::Context as _;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// namespace -> variant -> template
+type Templates = HashMap<String, HashMap<String, String>>;
+
+#[derive(Default, Debug, Deserialize)]
+struct TemplateFile {
+    /// Templates for specific channels
+    #[serde(default)]
+    channels: HashMap<String, Templates>,
+    #[serde(flatten)]
+    templates: Templates,
+}
+
+/// The templates used for responses
+///
+/// A user's templates file is layered over the defaults, and channels can override either of
+/// those. Like the config, `config_watcher` reloads the file when it changes, and so does
+/// `!templates reload`
+///
+/// Every template is checked against the responses when it is loaded
+#[derive(Debug)]
+pub struct TemplateStore {
+    defaults: Templates,
+    file: TemplateFile,
+    path: PathBuf,
+    registry: Registry,
+}
+
+impl TemplateStore {
+    pub fn new(defaults: &str, path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        Self::with_registry(defaults, path, crate::responses::registry()?)
+    }
+
+    pub(crate) fn with_registry(
+        defaults: &str,
+        path: impl Into<PathBuf>,
+        registry: Registry,
+    ) -> anyhow::Result<Self> {
+        let mut this = Self {
+            defaults: toml::from_str(defaults).with_context(|| "invalid default templates")?,
+            file: TemplateFile::default(),
+            path: path.into(),
+            registry,
+        };
+        this.reload()?;
+        Ok(this)
+    }
+
+    /// Loads the templates file again, a missing file is treated like an empty one
+    ///
+    /// If the file is invalid, the previous templates are kept
+    pub fn reload(&mut self) -> anyhow::Result<()> {
+        let file = match std::fs::read_to_string(&self.path) {
+            Ok(data) => toml::from_str(&data)
+                .with_context(|| format!("invalid templates in {}", self.path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => TemplateFile::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        self.check(&file)?;
+        self.file = file;
+        Ok(())
+    }
+
+    /// Checks the defaults and `file` against the responses
+    ///
+    /// Every response has to have a template, and every template has to belong to a response
+    /// and only use the fields it has
+    fn check(&self, file: &TemplateFile) -> anyhow::Result<()> {
+        let path = self.path.display();
+        let layers = std::iter::once(("defaults".to_string(), &self.defaults))
+            .chain(std::iter::once((path.to_string(), &file.templates)))
+            .chain(
+                file.channels
+                    .iter()
+                    .map(|(channel, templates)| (format!("{} ({})", path, channel), templates)),
+            );
+
+        let mut problems = vec![];
+        for (source, templates) in layers {
+            for (ns, vars) in templates {
+                for (var, data) in vars {
+                    let entry = match self.registry.get(ns, var) {
+                        Some(entry) => entry,
+                        None => {
+                            problems.push(format!("{}: unknown template {}.{}", source, ns, var));
+                            continue;
+                        }
+                    };
+                    let data = match formatting::render(data) {
+                        Ok(data) => formatting::strip(&data),
+                        Err(err) => {
+                            problems.push(format!("{}: {}.{}: {}", source, ns, var, err));
+                            continue;
+                        }
+                    };
+                    problems.extend(
+                        entry
+                            .check(&data)
+                            .into_iter()
+                            .map(|problem| format!("{}: {}.{}: {}", source, ns, var, problem)),
+                    );
+                }
+            }
+        }
+
+        for entry in self.registry.entries() {
+            let (ns, var) = (entry.namespace(), entry.variant());
+            let found = [&file.templates, &self.defaults]
+                .iter()
+                .any(|templates| templates.get(ns).and_then(|vars| vars.get(var)).is_some());
+            if !found {
+                problems.push(format!("missing template {}.{}", ns, var));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        problems.sort();
+        anyhow::bail!("invalid templates: {}", problems.join("; "))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the template for `channel`, falling back to the user's templates then the defaults
+    pub fn get(&self, channel: Option<&str>, ns: &str, var: &str) -> Option<&str> {
+        fn lookup<'a>(templates: &'a Templates, ns: &str, var: &str) -> Option<&'a str> {
+            templates.get(ns)?.get(var).map(String::as_str)
+        }
+
+        channel
+            .and_then(|channel| {
+                self.file
+                    .channels
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(channel))
+            })
+            .and_then(|(_, templates)| lookup(templates, ns, var))
+            .or_else(|| lookup(&self.file.templates, ns, var))
+            .or_else(|| lookup(&self.defaults, ns, var))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULTS: &str = r#"
+        [lag]
+        lag = "lag: ${lag}"
+        unknown = "lag hasn't been measured yet"
+    "#;
+
+    #[test]
+    fn layers() {
+        let path = std::env::temp_dir().join(format!("noye-templates-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let registry = Registry::default().with::<crate::responses::Lag>().unwrap();
+        let mut store = TemplateStore::with_registry(DEFAULTS, &path, registry).unwrap();
+        assert_eq!(store.get(None, "lag", "lag"), Some("lag: ${lag}"));
+        assert_eq!(store.get(Some("#test"), "lag", "lag"), Some("lag: ${lag}"));
+        assert_eq!(store.get(None, "lag", "other"), None);
+
+        std::fs::write(
+            &path,
+            r##"
+            [lag]
+            lag = "the lag is ${lag}"
+
+            [channels."#Terse".lag]
+            lag = "${lag}"
+            "##,
+        )
+        .unwrap();
+        store.reload().unwrap();
+
+        assert_eq!(store.get(None, "lag", "lag"), Some("the lag is ${lag}"));
+        assert_eq!(store.get(Some("#terse"), "lag", "lag"), Some("${lag}"));
+        assert_eq!(
+            store.get(Some("#other"), "lag", "lag"),
+            Some("the lag is ${lag}")
+        );
+        assert_eq!(
+            store.get(Some("#terse"), "lag", "unknown"),
+            Some("lag hasn't been measured yet")
+        );
+
+        // a broken file keeps the old templates
+        std::fs::write(&path, "[lag").unwrap();
+        assert!(store.reload().is_err());
+        assert_eq!(store.get(Some("#terse"), "lag", "lag"), Some("${lag}"));
+
+        // and so does one that doesn't match the responses
+        for data in &[
+            "[lag]\nlag = '${lagg}'",
+            "[lag]\nlag = '${lag}}'",
+            "[lag]\nlag = '{bold}${lag}'",
+            "[lag]\nlagg = '${lag}'",
+            "[channels.'#terse'.lag]\nlag = '${delay}'",
+        ] {
+            std::fs::write(&path, data).unwrap();
+            assert!(store.reload().is_err(), "{}", data);
+            assert_eq!(store.get(Some("#terse"), "lag", "lag"), Some("${lag}"));
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        store.reload().unwrap();
+        assert_eq!(store.get(Some("#terse"), "lag", "lag"), Some("lag: ${lag}"));
+    }
+}
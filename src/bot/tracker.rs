@@ -0,0 +1,389 @@
+use crate::irc::{Event, Prefix, Tags};
+
+use std::collections::{BTreeSet, HashMap};
+
+// TODO get these from RPL_ISUPPORT (PREFIX and CHANMODES)
+/// Status modes, and the prefix used for them in NAMES, highest first
+const STATUS: &[(char, char)] = &[('q', '~'), ('a', '&'), ('o', '@'), ('h', '%'), ('v', '+')];
+/// Modes that always take an argument
+const ALWAYS_ARG: &str = "beIk";
+/// Modes that only take an argument when they are being set
+const SET_ARG: &str = "l";
+
+/// Tracks the channels we're in, who is in them and what we know about those users
+///
+/// Nicknames and channels are compared case-insensitively
+#[derive(Default, Debug, Clone)]
+pub struct Tracker {
+    channels: HashMap<String, Channel>,
+    users: HashMap<String, User>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct Channel {
+    pub name: String,
+    pub topic: Option<String>,
+    members: HashMap<String, Member>,
+}
+
+impl Channel {
+    pub fn members(&self) -> impl Iterator<Item = &Member> + '_ {
+        self.members.values()
+    }
+
+    pub fn member(&self, nick: &str) -> Option<&Member> {
+        self.members.get(&key(nick))
+    }
+
+    pub fn contains(&self, nick: &str) -> bool {
+        self.members.contains_key(&key(nick))
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct Member {
+    pub nick: String,
+    /// The status modes (e.g. `o` and `v`) the user has in the channel
+    pub modes: BTreeSet<char>,
+}
+
+impl Member {
+    /// Whether the user is an operator (or higher) in the channel
+    pub fn is_op(&self) -> bool {
+        self.modes.iter().any(|m| "qao".contains(*m))
+    }
+
+    pub fn is_voiced(&self) -> bool {
+        self.modes.contains(&'v')
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct User {
+    pub nick: String,
+    pub user: Option<String>,
+    pub host: Option<String>,
+    pub account: Option<String>,
+}
+
+impl User {
+    /// The `nick!user@host` for this user, if we've seen it
+    pub fn hostmask(&self) -> Option<String> {
+        match (&self.user, &self.host) {
+            (Some(user), Some(host)) => Some(format!("{}!{}@{}", self.nick, user, host)),
+            _ => None,
+        }
+    }
+}
+
+impl Tracker {
+    pub fn channels(&self) -> impl Iterator<Item = &Channel> + '_ {
+        self.channels.values()
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.get(&key(name))
+    }
+
+    /// Everyone we share a channel with
+    pub fn users(&self) -> impl Iterator<Item = &User> + '_ {
+        self.users.values()
+    }
+
+    pub fn user(&self, nick: &str) -> Option<&User> {
+        self.users.get(&key(nick))
+    }
+
+    /// Whether `nick` is in `channel`
+    pub fn is_on(&self, channel: &str, nick: &str) -> bool {
+        self.channel(channel).filter(|c| c.contains(nick)).is_some()
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.channels.clear();
+        self.users.clear();
+    }
+
+    /// Updates the tracker from an event, `me` is our current nickname
+    pub(super) fn apply(&mut self, me: &str, prefix: Option<&Prefix>, tags: &Tags, event: &Event) {
+        self.update(me, event);
+
+        // only the users in our channels are kept
+        if let Some(Prefix::User { nick, user, host }) = prefix {
+            if let Some(entry) = self.users.get_mut(&key(nick)) {
+                entry.user.replace(user.clone());
+                entry.host.replace(host.clone());
+                if let Some(account) = tags.account() {
+                    entry.account.replace(account.to_string());
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, me: &str, event: &Event) {
+        match event {
+            Event::Join { nick, channel } => {
+                let channel = self
+                    .channels
+                    .entry(key(channel))
+                    .or_insert_with(|| Channel {
+                        name: channel.clone(),
+                        ..Channel::default()
+                    });
+                if key(nick) == key(me) {
+                    channel.members.clear();
+                }
+                self.users.entry(key(nick)).or_default().nick = nick.clone();
+                channel.members.insert(
+                    key(nick),
+                    Member {
+                        nick: nick.clone(),
+                        ..Member::default()
+                    },
+                );
+            }
+
+            Event::Part { nick, channel, .. } | Event::Kick { nick, channel, .. } => {
+                if key(nick) == key(me) {
+                    self.channels.remove(&key(channel));
+                } else if let Some(channel) = self.channels.get_mut(&key(channel)) {
+                    channel.members.remove(&key(nick));
+                }
+                self.forget_users();
+            }
+
+            Event::Quit { nick, .. } => {
+                for channel in self.channels.values_mut() {
+                    channel.members.remove(&key(nick));
+                }
+                self.users.remove(&key(nick));
+            }
+
+            Event::Nick { old, new } => {
+                for channel in self.channels.values_mut() {
+                    if let Some(mut member) = channel.members.remove(&key(old)) {
+                        member.nick = new.clone();
+                        channel.members.insert(key(new), member);
+                    }
+                }
+                if let Some(mut user) = self.users.remove(&key(old)) {
+                    user.nick = new.clone();
+                    self.users.insert(key(new), user);
+                }
+            }
+
+            Event::Names { channel, names } => {
+                let channel = match self.channels.get_mut(&key(channel)) {
+                    Some(channel) => channel,
+                    None => return,
+                };
+                for name in names {
+                    let (modes, name) = parse_status(name);
+                    // userhost-in-names gives us the full hostmask
+                    let nick = match (name.find('!'), name.find('@')) {
+                        (Some(bang), Some(at)) if bang < at => {
+                            let entry = self.users.entry(key(&name[..bang])).or_default();
+                            entry.user.replace(name[bang + 1..at].to_string());
+                            entry.host.replace(name[at + 1..].to_string());
+                            name[..bang].to_string()
+                        }
+                        _ => name.to_string(),
+                    };
+                    self.users.entry(key(&nick)).or_default().nick = nick.clone();
+                    channel.members.insert(key(&nick), Member { nick, modes });
+                }
+            }
+
+            Event::Mode {
+                target,
+                modes,
+                args,
+                ..
+            } => {
+                if let Some(channel) = self.channels.get_mut(&key(target)) {
+                    apply_modes(channel, modes, args);
+                }
+            }
+
+            Event::Topic { channel, topic, .. } => {
+                if let Some(channel) = self.channels.get_mut(&key(channel)) {
+                    channel.topic = topic.clone();
+                }
+            }
+
+            Event::TopicReply { channel, topic } => {
+                if let Some(channel) = self.channels.get_mut(&key(channel)) {
+                    channel.topic.replace(topic.clone());
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Drops the users that aren't in any of our channels
+    fn forget_users(&mut self) {
+        let channels = &self.channels;
+        self.users.retain(|nick, _| {
+            channels
+                .values()
+                .any(|channel| channel.members.contains_key(nick))
+        });
+    }
+}
+
+fn key(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+/// Splits the status prefixes (e.g. `@+`) from a name in a NAMES reply
+fn parse_status(name: &str) -> (BTreeSet<char>, &str) {
+    let mut modes = BTreeSet::new();
+    for (i, ch) in name.char_indices() {
+        match STATUS.iter().find(|(_, prefix)| *prefix == ch) {
+            Some((mode, _)) => modes.insert(*mode),
+            None => return (modes, &name[i..]),
+        };
+    }
+    (modes, "")
+}
+
+fn apply_modes(channel: &mut Channel, modes: &str, args: &[String]) {
+    let mut args = args.iter();
+    let mut set = true;
+    for ch in modes.chars() {
+        match ch {
+            '+' => set = true,
+            '-' => set = false,
+            ch if STATUS.iter().any(|(mode, _)| *mode == ch) => {
+                let member = args
+                    .next()
+                    .and_then(|nick| channel.members.get_mut(&key(nick)));
+                if let Some(member) = member {
+                    if set {
+                        member.modes.insert(ch);
+                    } else {
+                        member.modes.remove(&ch);
+                    }
+                }
+            }
+            ch if ALWAYS_ARG.contains(ch) || (set && SET_ARG.contains(ch)) => {
+                args.next();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc::RawMessage;
+
+    fn feed(tracker: &mut Tracker, lines: &[&str]) {
+        for line in lines {
+            let msg = RawMessage::parse(&format!("{}\r\n", line)).unwrap();
+            let event = Event::from_raw(&msg).unwrap();
+            tracker.apply("noye", msg.prefix.as_ref(), &msg.tags, &event);
+        }
+    }
+
+    #[test]
+    fn membership() {
+        let mut tracker = Tracker::default();
+        feed(
+            &mut tracker,
+            &[
+                ":noye!~noye@bot.example.com JOIN #test",
+                ":irc.example.com 353 noye = #test :noye @museun +Other",
+                ":irc.example.com 366 noye #test :End of /NAMES list.",
+                ":irc.example.com 332 noye #test :a topic",
+                ":someone!~s@example.com JOIN #Test",
+            ],
+        );
+
+        let channel = tracker.channel("#TEST").unwrap();
+        assert_eq!(channel.name, "#test");
+        assert_eq!(channel.topic.as_deref(), Some("a topic"));
+        assert_eq!(channel.members().count(), 4);
+        assert!(channel.member("museun").unwrap().is_op());
+        assert!(channel.member("other").unwrap().is_voiced());
+        assert!(!channel.member("someone").unwrap().is_op());
+        assert_eq!(
+            tracker.user("someone").unwrap().hostmask().as_deref(),
+            Some("someone!~s@example.com")
+        );
+
+        feed(
+            &mut tracker,
+            &[
+                ":someone!~s@example.com NICK :another",
+                ":museun!~m@localhost KICK #test Other :bye",
+                ":museun!~m@localhost QUIT :leaving",
+            ],
+        );
+
+        assert!(tracker.is_on("#test", "another"));
+        assert!(!tracker.is_on("#test", "someone"));
+        assert!(!tracker.is_on("#test", "other"));
+        assert!(!tracker.is_on("#test", "museun"));
+        assert!(tracker.user("other").is_none());
+        assert_eq!(
+            tracker.user("another").unwrap().hostmask().as_deref(),
+            Some("another!~s@example.com")
+        );
+
+        feed(&mut tracker, &[":noye!~noye@bot.example.com PART #test"]);
+        assert!(tracker.channel("#test").is_none());
+        assert!(tracker.user("another").is_none());
+    }
+
+    #[test]
+    fn modes() {
+        let mut tracker = Tracker::default();
+        feed(
+            &mut tracker,
+            &[
+                ":noye!~noye@bot.example.com JOIN #test",
+                ":irc.example.com 353 noye = #test :noye @+museun other",
+                ":museun!~m@localhost MODE #test +lbo-v 10 *!*@spam other museun",
+            ],
+        );
+
+        let channel = tracker.channel("#test").unwrap();
+        let museun = channel.member("museun").unwrap();
+        assert!(museun.is_op());
+        assert!(!museun.is_voiced());
+        assert!(channel.member("other").unwrap().is_op());
+
+        feed(&mut tracker, &[":museun!~m@localhost MODE #test -lo other"]);
+        assert!(!tracker
+            .channel("#test")
+            .unwrap()
+            .member("other")
+            .unwrap()
+            .is_op());
+    }
+
+    #[test]
+    fn userhost_in_names() {
+        let mut tracker = Tracker::default();
+        feed(
+            &mut tracker,
+            &[
+                ":noye!~noye@bot.example.com JOIN #test",
+                ":irc.example.com 353 noye = #test :@museun!~m@localhost noye!~noye@bot.example.com",
+            ],
+        );
+        assert!(tracker
+            .channel("#test")
+            .unwrap()
+            .member("museun")
+            .unwrap()
+            .is_op());
+        assert_eq!(
+            tracker.user("museun").unwrap().hostmask().as_deref(),
+            Some("museun!~m@localhost")
+        );
+    }
+}
@@ -0,0 +1,72 @@
+/// Finds the command in a message, returning everything after whatever triggered it
+///
+/// A command is triggered by one of the `prefixes` (e.g. `!foo`), by addressing the
+/// bot by its `nick` (e.g. `noye: foo` or `noye, foo`) or, in a private message, by
+/// the message alone
+pub fn strip<'a>(
+    data: &'a str,
+    prefixes: &[String],
+    nick: Option<&str>,
+    private: bool,
+) -> Option<&'a str> {
+    let command = |rest: &'a str| Some(rest).filter(|s| !s.is_empty() && !s.starts_with(' '));
+
+    if let Some(rest) = prefixes
+        .iter()
+        .filter(|prefix| !prefix.is_empty())
+        .find_map(|prefix| data.strip_prefix(prefix.as_str()))
+    {
+        return command(rest);
+    }
+
+    if let Some(nick) = nick {
+        let addressed = data
+            .get(..nick.len())
+            .filter(|head| head.eq_ignore_ascii_case(nick))
+            .and_then(|_| data[nick.len()..].strip_prefix(&[':', ','][..]));
+        if let Some(rest) = addressed {
+            return command(rest.trim_start());
+        }
+    }
+
+    if private {
+        return command(data);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        let prefixes = vec!["!".to_string(), "noye.".to_string()];
+        let strip = |data| strip(data, &prefixes, None, false);
+
+        assert_eq!(strip("!foo bar"), Some("foo bar"));
+        assert_eq!(strip("noye.foo"), Some("foo"));
+        assert_eq!(strip("! foo"), None);
+        assert_eq!(strip("!"), None);
+        assert_eq!(strip(".foo"), None);
+        assert_eq!(strip("foo"), None);
+    }
+
+    #[test]
+    fn addressed() {
+        let prefixes = vec![".".to_string()];
+        let strip = |data, private| strip(data, &prefixes, Some("noye"), private);
+
+        assert_eq!(strip("noye: foo bar", false), Some("foo bar"));
+        assert_eq!(strip("Noye, foo", false), Some("foo"));
+        assert_eq!(strip("noye:foo", false), Some("foo"));
+        assert_eq!(strip("noye foo", false), None);
+        assert_eq!(strip("noyes: foo", false), None);
+        assert_eq!(strip("noye:", false), None);
+        assert_eq!(strip("!foo", false), None);
+
+        assert_eq!(strip("foo bar", true), Some("foo bar"));
+        assert_eq!(strip(".foo", true), Some("foo"));
+        assert_eq!(strip("noye: foo", true), Some("foo"));
+    }
+}
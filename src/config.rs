@@ -1,54 +1,121 @@
 use anyhow::Context as _;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::broadcast;
 
+/// The current config, as one network sees it
+///
+/// The `irc_config` of this is the config for the network. This is kept up to date by
+/// `config_watcher`, which sends a `ConfigChanged` to every subscriber when the config is replaced
+#[derive(Clone)]
 pub struct CachedConfig {
+    full: Arc<Config>,
     config: Config,
-    last: std::time::SystemTime,
-    path: std::path::PathBuf,
+    network: Option<String>,
+    path: PathBuf,
+    changes: broadcast::Sender<ConfigChanged>,
 }
 
 impl CachedConfig {
+    /// The config for the first network
     pub fn new(config: Config, path: impl Into<PathBuf>) -> Self {
+        let network = config.networks()[0].network.clone();
+        let (changes, _) = broadcast::channel(16);
         Self {
-            config,
+            config: config
+                .for_network(network.as_deref())
+                .unwrap_or_else(|| config.clone()),
+            full: Arc::new(config),
+            network,
             path: path.into(),
-            last: std::time::SystemTime::now(),
+            changes,
         }
     }
 
-    pub async fn get(&mut self) -> anyhow::Result<&Config> {
-        if cfg!(not(test)) {
-            let md = std::fs::metadata(&self.path)?.modified()?;
+    /// The config for another network, this shares the subscribers with `self`
+    pub fn for_network(&self, network: Option<&str>) -> anyhow::Result<Self> {
+        let config = self
+            .full
+            .for_network(network)
+            .ok_or_else(|| anyhow::anyhow!("unknown network: {}", network.unwrap_or_default()))?;
+        Ok(Self {
+            config,
+            network: network.map(ToString::to_string),
+            ..self.clone()
+        })
+    }
 
-            if self.last < md {
-                self.config = Config::load(&self.path).await?;
-                self.last = md;
-            }
-        }
+    pub fn get(&self) -> &Config {
+        &self.config
+    }
 
-        Ok(&self.config)
+    pub fn get_mut(&mut self) -> &mut Config {
+        &mut self.config
     }
 
-    pub async fn get_mut(&mut self) -> anyhow::Result<&mut Config> {
-        if cfg!(not(test)) {
-            let md = std::fs::metadata(&self.path)?.modified()?;
+    /// The config for every network
+    pub fn full(&self) -> &Arc<Config> {
+        &self.full
+    }
 
-            if self.last < md {
-                self.config = Config::load(&self.path).await?;
-                self.last = md;
-            }
-        }
+    pub fn network(&self) -> Option<&str> {
+        self.network.as_deref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Swaps in a new config, returning the old one
+    ///
+    /// The network has to be in the new config. The subscribers aren't told about this, see
+    /// `announce`
+    pub fn replace(&mut self, config: Arc<Config>) -> anyhow::Result<Arc<Config>> {
+        self.config = config.for_network(self.network()).ok_or_else(|| {
+            anyhow::anyhow!("network was removed: {}", self.config.irc_config.name())
+        })?;
+        Ok(std::mem::replace(&mut self.full, config))
+    }
+
+    /// Tells the subscribers that the config has changed from `old`
+    pub fn announce(&self, old: Arc<Config>) {
+        let changed = ConfigChanged {
+            old,
+            new: self.full.clone(),
+        };
+        // it doesn't matter if no one is listening
+        let _ = self.changes.send(changed);
+    }
 
-        Ok(&mut self.config)
+    pub fn subscribe(&self) -> broadcast::Receiver<ConfigChanged> {
+        self.changes.subscribe()
     }
 }
 
+/// Sent when the config file has changed
+#[derive(Debug, Clone)]
+pub struct ConfigChanged {
+    pub old: Arc<Config>,
+    pub new: Arc<Config>,
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// The network to connect to, this is ignored if there are any `networks`
+    #[serde(default)]
     pub irc_config: Irc,
+    /// The networks to connect to
+    #[serde(default)]
+    pub networks: Vec<Irc>,
     pub modules: Modules,
     pub web: Web,
+    /// Cooldowns for commands, these replace the ones the modules provide
+    #[serde(default)]
+    pub cooldowns: HashMap<String, Cooldown>,
 }
 
 impl Config {
@@ -56,8 +123,99 @@ impl Config {
         let data = tokio::fs::read_to_string(&path)
             .await
             .with_context(|| "cannot read config file")?;
-        toml::from_str(&data).with_context(|| "invalid config toml")
+        let config: Self = toml::from_str(&data).with_context(|| "invalid config toml")?;
+        config.validate()?;
+
+        for irc in config
+            .networks()
+            .into_iter()
+            .filter(|irc| !irc.owners.is_empty())
+        {
+            log::warn!(
+                "'owners' for network '{}' is deprecated, move them to 'permissions.owner'",
+                irc.name()
+            );
+        }
+        Ok(config)
+    }
+
+    /// Checks the values that can't be checked while deserializing
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let durations = &[
+            ("modules.repost.staleness", &self.modules.repost.staleness),
+            ("modules.pictures.cooldown", &self.modules.pictures.cooldown),
+            (
+                "modules.pictures.quiet_time",
+                &self.modules.pictures.quiet_time,
+            ),
+        ];
+        for (name, value) in durations {
+            simple_duration_parse::parse_secs(value)
+                .with_context(|| format!("invalid duration for {}: '{}'", name, value))?;
+        }
+
+        for (command, cooldown) in &self.cooldowns {
+            crate::Cooldown::from_config(cooldown)
+                .with_context(|| format!("invalid cooldown for {}", command))?;
+        }
+
+        let mut names = std::collections::HashSet::new();
+        for irc in self.networks() {
+            if !names.insert(irc.network.as_deref()) {
+                anyhow::bail!("network '{}' is listed more than once", irc.name())
+            }
+
+            // anyone can use a nick, so it can't give out a role
+            let is_mask =
+                |owner: &str| owner.contains(':') || (owner.contains('!') && owner.contains('@'));
+            let nick = irc.owners.iter().find(|owner| !is_mask(owner));
+            if let Some(nick) = nick {
+                anyhow::bail!(
+                    "'owners' for network '{}' has the nick '{}', use an account name \
+                     ('account:name'), 'q:name' or a full hostmask in 'permissions.owner' instead",
+                    irc.name(),
+                    nick
+                )
+            }
+        }
+        Ok(())
     }
+
+    /// The networks to connect to, there is always at least one
+    pub fn networks(&self) -> Vec<&Irc> {
+        if self.networks.is_empty() {
+            return vec![&self.irc_config];
+        }
+        self.networks.iter().collect()
+    }
+
+    pub fn network(&self, network: Option<&str>) -> Option<&Irc> {
+        self.networks()
+            .into_iter()
+            .find(|irc| irc.network.as_deref() == network)
+    }
+
+    /// This config with the `irc_config` for `network`
+    pub fn for_network(&self, network: Option<&str>) -> Option<Self> {
+        let irc_config = self.network(network)?.clone();
+        Some(Self {
+            irc_config,
+            ..self.clone()
+        })
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Cooldown {
+    /// How often anyone can use the command
+    pub global: Option<String>,
+    /// How often the command can be used in a channel
+    pub channel: Option<String>,
+    /// How often a user can use the command
+    pub user: Option<String>,
+    /// Tell the user when they've been throttled
+    #[serde(default)]
+    pub reply: bool,
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -68,22 +226,263 @@ pub struct Web {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Irc {
+    /// The name of the network, which keeps its data apart from the other networks
+    ///
+    /// Only one network can be left unnamed, this is what a config without `networks` has
+    #[serde(default)]
+    pub network: Option<String>,
     pub address: String,
+    #[serde(default)]
+    pub tls: Option<Tls>,
 
     pub name: String,
     pub user: String,
     pub real: String,
 
     pub channels: Vec<String>,
+    #[serde(default)]
+    pub commands: Commands,
+    /// Deprecated, use `permissions.owner` instead
+    ///
+    /// These are masks like in `permissions.owner`, but a bare name isn't allowed. It used to be a
+    /// nick, which anyone could use
+    #[serde(default)]
     pub owners: Vec<String>,
+    #[serde(default)]
+    pub permissions: Permissions,
+
+    /// The most lines a single response can be split into
+    #[serde(default)]
+    pub max_lines: Option<usize>,
+    #[serde(default)]
+    pub formatting: Formatting,
 
     pub q_pass: Option<String>,
     pub q_name: Option<String>,
+
+    #[serde(default)]
+    pub auth: Option<Auth>,
+    #[serde(default = "default_auth_timeout")]
+    pub auth_timeout: String,
+
+    #[serde(default = "default_capabilities")]
+    pub capabilities: Vec<String>,
+
+    #[serde(default)]
+    pub reconnect: Reconnect,
+    #[serde(default)]
+    pub ping: Ping,
+    #[serde(default)]
+    pub throttle: Throttle,
+    #[serde(default)]
+    pub quit: Quit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Commands {
+    /// What starts a command, e.g. `!` for `!help`
+    pub prefixes: Vec<String>,
+    /// Prefixes for specific channels, these replace the default ones
+    pub channels: HashMap<String, Vec<String>>,
+    /// Whether addressing the bot (e.g. `noye: help`) runs a command
+    pub addressed: bool,
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self {
+            prefixes: vec!["!".into()],
+            channels: Default::default(),
+            addressed: true,
+        }
+    }
+}
+
+impl Commands {
+    /// The prefixes used in `channel`
+    pub fn prefixes(&self, channel: &str) -> &[String] {
+        self.channels
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(channel))
+            .map(|(_, prefixes)| prefixes)
+            .unwrap_or(&self.prefixes)
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Formatting {
+    /// Channels where colours and formatting are removed, e.g. because they are `+c`
+    pub strip: Vec<String>,
+}
+
+impl Formatting {
+    pub fn strip(&self, channel: &str) -> bool {
+        self.strip.iter().any(|c| c.eq_ignore_ascii_case(channel))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Throttle {
+    /// How many lines can be sent at once
+    pub burst: u32,
+    /// How long it takes for another line to be allowed
+    pub refill: String,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self {
+            burst: 5,
+            refill: "2s".into(),
+        }
+    }
+}
+
+/// Permission masks for each role, see `Identity::matches` for the format
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    pub owner: Vec<String>,
+    pub admin: Vec<String>,
+    pub trusted: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ping {
+    /// How long the connection can be idle before we send a PING
+    pub interval: String,
+    /// How long to wait for a PONG before reconnecting
+    pub timeout: String,
+}
+
+impl Default for Ping {
+    fn default() -> Self {
+        Self {
+            interval: "60s".into(),
+            timeout: "60s".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reconnect {
+    pub min_delay: String,
+    pub max_delay: String,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            min_delay: "5s".into(),
+            max_delay: "5m".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quit {
+    /// The `QUIT` message, when one isn't given
+    pub message: String,
+    /// How long the running commands have to finish before we quit
+    pub timeout: String,
+}
+
+impl Default for Quit {
+    fn default() -> Self {
+        Self {
+            message: "bye".into(),
+            timeout: "5s".into(),
+        }
+    }
+}
+
+impl Irc {
+    /// The name of the network, for logging
+    pub fn name(&self) -> &str {
+        self.network.as_deref().unwrap_or("default")
+    }
+
+    /// The configured permissions, including the deprecated `owners`
+    pub fn permissions(&self) -> Permissions {
+        let mut permissions = self.permissions.clone();
+        permissions.owner.extend(self.owners.iter().cloned());
+        permissions
+    }
+
+    /// The configured authentication method
+    ///
+    /// This falls back to the `q_name` and `q_pass` fields if `auth` isn't set
+    pub fn auth(&self) -> Auth {
+        if let Some(auth) = &self.auth {
+            return auth.clone();
+        }
+
+        match (&self.q_name, &self.q_pass) {
+            (Some(name), Some(pass)) if !name.is_empty() && !pass.is_empty() => Auth::Q {
+                name: name.clone(),
+                pass: pass.clone(),
+            },
+            _ => Auth::None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Tls {
+    /// The name to verify the server certificate against, defaults to the host of the address
+    pub domain: Option<String>,
+    /// A PEM or DER encoded certificate to trust in addition to the system roots
+    pub ca_file: Option<String>,
+    /// The only certificate authorities to trust, as a PEM file with one or more certificates (or a
+    /// single DER encoded one)
+    ///
+    /// When this is set the system roots aren't trusted, so the server's certificate has to be
+    /// signed by one of these. This pins the CA rather than the server's certificate
+    pub ca_bundle: Option<String>,
+    /// A PKCS #12 archive used as the client certificate, e.g. for SASL EXTERNAL
+    pub client_cert: Option<String>,
+    pub client_cert_pass: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Auth {
+    None,
+    Q { name: String, pass: String },
+    NickServ { name: Option<String>, pass: String },
+    SaslPlain { name: String, pass: String },
+    SaslExternal,
+}
+
+impl Auth {
+    pub fn is_sasl(&self) -> bool {
+        match self {
+            Self::SaslPlain { .. } | Self::SaslExternal => true,
+            _ => false,
+        }
+    }
+}
+
+fn default_auth_timeout() -> String {
+    "15s".into()
+}
+
+fn default_capabilities() -> Vec<String> {
+    ["server-time", "account-tag", "message-tags", "echo-message"]
+        .iter()
+        .map(ToString::to_string)
+        .collect()
 }
 
 // TODO split this up into sub-configuration
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Modules {
+    /// Which modules are allowed in a channel, channels that aren't listed get every module
+    #[serde(default)]
+    pub channels: HashMap<String, ChannelModules>,
+
     pub link_size: LinkSize,
     pub repost: Repost,
     pub restart: Restart,
@@ -93,6 +492,34 @@ pub struct Modules {
     pub gfycat: Gfycat,
 }
 
+impl Modules {
+    /// Whether `module` is enabled in `channel`, ignoring any changes made at runtime
+    pub fn is_enabled(&self, channel: &str, module: &str) -> bool {
+        let ChannelModules { allow, deny } = match self
+            .channels
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(channel))
+        {
+            Some((_, modules)) => modules,
+            None => return true,
+        };
+
+        !deny.iter().any(|m| m == module)
+            && allow
+                .as_ref()
+                .map_or(true, |allow| allow.iter().any(|m| m == module))
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelModules {
+    /// When set, only these modules are enabled
+    pub allow: Option<Vec<String>>,
+    /// These modules are disabled, even if they are in `allow`
+    pub deny: Vec<String>,
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct LinkSize {
     pub size_limit: u64,
@@ -105,7 +532,21 @@ pub struct Repost {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Restart {
+    /// Where `noye-supervisor` listens for restart requests
     pub address: String,
+    /// How `noye-supervisor` runs the bot, the `noye` next to it is used if this isn't set
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Run before the bot is restarted, e.g. `cargo build --release`
+    #[serde(default)]
+    pub rebuild: Option<String>,
+    /// Where the output of a crash is kept, the bot logs it when it starts again
+    #[serde(default = "default_crash_log")]
+    pub crash_log: String,
+}
+
+fn default_crash_log() -> String {
+    "noye-crash.log".into()
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -128,7 +569,7 @@ pub struct Pictures {
     pub directories: HashMap<String, PicturesItem>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PicturesItem {
     pub directory: String,
     pub command: String,
@@ -0,0 +1,85 @@
+use crate::config;
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+mod supervisor;
+pub use supervisor::Supervisor;
+
+mod throttle;
+mod tls;
+
+/// A connection to an IRC server, optionally over TLS
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(tokio_tls::TlsStream<TcpStream>),
+}
+
+impl Connection {
+    pub async fn connect(config: &config::Irc) -> anyhow::Result<Self> {
+        let config::Irc { address, tls, .. } = config;
+
+        log::info!("connecting to {}", address);
+        let stream = TcpStream::connect(address).await?;
+        match tls {
+            Some(tls) => tls::connect(stream, address, tls).await.map(Self::Tls),
+            None => {
+                log::warn!("connection to {} is not using tls", address);
+                Ok(Self::Plain(stream))
+            }
+        }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+fn parse_duration(input: &str, default: Duration) -> Duration {
+    simple_duration_parse::parse_secs(input)
+        .map(Duration::from_secs)
+        .unwrap_or(default)
+}
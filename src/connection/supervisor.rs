@@ -0,0 +1,370 @@
+use super::{parse_duration, throttle::Throttle, Connection};
+use crate::{
+    bot::{Stop, Tasks},
+    config,
+    util::inspect_err,
+    Responder, Runner,
+};
+
+use tokio::{
+    io::BufStream,
+    prelude::*,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// Keeps the bot connected, reconnecting with an exponential backoff
+///
+/// The `Runner` (and its `State`) outlive each connection
+pub struct Supervisor<R> {
+    runner: Runner<R>,
+    responder: R,
+    rx: mpsc::Receiver<String>,
+}
+
+enum Disconnect {
+    Quit,
+    Lost,
+    Timeout,
+}
+
+impl<R: Responder + Send + 'static> Supervisor<R> {
+    pub fn new(runner: Runner<R>, responder: R, rx: mpsc::Receiver<String>) -> Self {
+        Self {
+            runner,
+            responder,
+            rx,
+        }
+    }
+
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let mut backoff = None;
+        let quit = self.runner.quit.clone();
+
+        loop {
+            if quit.stopping().is_some() {
+                break Ok(());
+            }
+
+            let config = self.runner.state.lock().await.config()?.irc_config.clone();
+            let backoff = backoff.get_or_insert_with(|| Backoff::new(&config.reconnect));
+
+            let (registered, result) = match Connection::connect(&config).await {
+                Ok(conn) => self.session(conn, &config).await,
+                Err(err) => (false, Err(err)),
+            };
+
+            match result {
+                Ok(Disconnect::Quit) => break Ok(()),
+                Ok(Disconnect::Lost) => log::warn!("connection was lost"),
+                Ok(Disconnect::Timeout) => log::warn!("connection timed out"),
+                Err(err) => inspect_err(&err, || "connection"),
+            }
+
+            // only back off if we never registered
+            if registered {
+                backoff.reset();
+            }
+
+            let delay = backoff.next();
+            log::info!("reconnecting in {} seconds", delay.as_secs());
+            tokio::select! {
+                _ = tokio::time::delay_for(delay) => {}
+                _ = quit.wait() => break Ok(()),
+            }
+        }
+    }
+
+    /// Runs a single connection, returning whether we registered on it and how it ended
+    async fn session(
+        &mut self,
+        conn: Connection,
+        config: &config::Irc,
+    ) -> (bool, anyhow::Result<Disconnect>) {
+        if let Err(err) = self.runner.reset().await {
+            return (false, Err(err));
+        }
+        let result = self.converse(conn, config).await;
+        self.runner.ready.reset();
+        // this is only set once the server has welcomed us
+        (!self.runner.nick.is_empty(), result)
+    }
+
+    async fn converse(
+        &mut self,
+        conn: Connection,
+        config: &config::Irc,
+    ) -> anyhow::Result<Disconnect> {
+        // anything queued for the old connection is stale now
+        while self.rx.try_recv().is_ok() {}
+
+        let mut stream = BufStream::new(conn);
+        let config::Irc {
+            name, user, real, ..
+        } = config;
+        for line in &[
+            "CAP LS 302".to_string(),
+            format!("NICK {}", name),
+            format!("USER {} * 8 :{}", user, real),
+        ] {
+            stream.write_all(line.as_bytes()).await?;
+            stream.write_all(b"\r\n").await?;
+        }
+        stream.flush().await?;
+
+        let config::Ping { interval, timeout } = &config.ping;
+        let interval = parse_duration(interval, Duration::from_secs(60));
+        let timeout = parse_duration(timeout, Duration::from_secs(60));
+
+        let (quit, latency) = (self.runner.quit.clone(), self.runner.latency.clone());
+        let connection_ready = self.runner.ready.clone();
+        let mut last_read = Instant::now();
+        let mut token = 0_u64;
+
+        let mut throttle = Throttle::new(&config.throttle);
+        // these are told once the lines that were queued before they asked have been sent
+        let mut waiting = vec![];
+
+        let mut line = String::new();
+        loop {
+            let deadline = match latency.pending_since() {
+                Some(sent) => sent + timeout,
+                None => last_read + interval,
+            };
+            let ready = throttle.next_ready();
+
+            tokio::select! {
+                read = stream.read_line(&mut line) => {
+                    if read? == 0 {
+                        break Ok(Disconnect::Lost);
+                    }
+                    last_read = Instant::now();
+                    if let Err(err) = self.runner.handle(&line, self.responder.clone()).await {
+                        inspect_err(&err, || format!("handling: {}", line.escape_debug()));
+                    }
+                    line.clear();
+                }
+                Some(data) = self.rx.recv() => {
+                    throttle.push(data);
+                    send_ready(&mut stream, &mut throttle).await?;
+                }
+                _ = connection_ready.requested() => {
+                    // they asked after queueing their lines, so those are in the channel now
+                    waiting.extend(connection_ready.take_waiting());
+                    while let Ok(data) = self.rx.try_recv() {
+                        throttle.push(data);
+                    }
+                    send_ready(&mut stream, &mut throttle).await?;
+                }
+                _ = tokio::time::delay_until(ready.unwrap_or(deadline)), if ready.is_some() => {
+                    send_ready(&mut stream, &mut throttle).await?;
+                }
+                _ = tokio::time::delay_until(deadline) => {
+                    if latency.pending_since().is_some() {
+                        break Ok(Disconnect::Timeout);
+                    }
+                    token += 1;
+                    let token = format!("noye-{}", token);
+                    latency.ping(&token);
+                    stream.write_all(format!("PING :{}\r\n", token).as_bytes()).await?;
+                    stream.flush().await?;
+                }
+                stop = quit.wait() => {
+                    let tasks = &self.runner.tasks;
+                    leave(&mut stream, &mut throttle, &mut self.rx, tasks, &config.quit, stop).await?;
+                    break Ok(Disconnect::Quit);
+                }
+            }
+
+            if throttle.next_ready().is_none() {
+                for tx in waiting.drain(..) {
+                    let _ = tx.send(());
+                }
+            }
+        }
+    }
+}
+
+/// Lets the running handlers finish, then sends everything they queued before quitting
+async fn leave(
+    stream: &mut (impl AsyncWrite + Unpin),
+    throttle: &mut Throttle,
+    rx: &mut mpsc::Receiver<String>,
+    tasks: &Tasks,
+    config: &config::Quit,
+    stop: Stop,
+) -> anyhow::Result<()> {
+    let timeout = parse_duration(&config.timeout, Duration::from_secs(5));
+    let deadline = Instant::now() + timeout;
+    log::info!("quitting, waiting for {} handlers", tasks.active());
+
+    // keep taking their lines so they can't block on a full queue
+    loop {
+        tokio::select! {
+            _ = tasks.idle() => break,
+            Some(data) = rx.recv() => throttle.push(data),
+            _ = tokio::time::delay_until(deadline) => {
+                log::warn!("{} handlers were still running", tasks.active());
+                break;
+            }
+        }
+    }
+    while let Ok(data) = rx.try_recv() {
+        throttle.push(data);
+    }
+
+    for line in throttle.drain() {
+        stream.write_all(line.as_bytes()).await?;
+    }
+    let reason = stop.reason.as_deref().unwrap_or(&config.message);
+    stream
+        .write_all(format!("QUIT :{}\r\n", reason).as_bytes())
+        .await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn send_ready(
+    stream: &mut (impl AsyncWrite + Unpin),
+    throttle: &mut Throttle,
+) -> anyhow::Result<()> {
+    let now = Instant::now();
+    while let Some(line) = throttle.pop(now) {
+        stream.write_all(line.as_bytes()).await?;
+    }
+    stream.flush().await?;
+    Ok(())
+}
+
+struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    fn new(config: &config::Reconnect) -> Self {
+        let min = parse_duration(&config.min_delay, Duration::from_secs(5));
+        let max = parse_duration(&config.max_delay, Duration::from_secs(5 * 60)).max(min);
+        Self {
+            min,
+            max,
+            current: min,
+        }
+    }
+
+    fn next(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn leave_after_handlers() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let tasks = Tasks::default();
+        for (delay, reply) in &[(10, "first"), (50, "second")] {
+            let (delay, reply, mut tx) = (*delay, *reply, tx.clone());
+            tasks.spawn(async move {
+                tokio::time::delay_for(Duration::from_millis(delay)).await;
+                let _ = tx.send(format!("PRIVMSG #test :{}\r\n", reply)).await;
+            });
+        }
+        // this one won't finish in time
+        tasks.spawn(futures::future::pending::<()>());
+
+        let config = config::Quit {
+            message: "bye".into(),
+            timeout: "1s".into(),
+        };
+        let mut throttle = Throttle::new(&config::Throttle {
+            burst: 1,
+            refill: "1m".into(),
+        });
+        throttle.push("PRIVMSG #test :queued\r\n".into());
+        let mut out = vec![];
+        let stop = Stop {
+            reason: Some("see you".into()),
+        };
+        leave(&mut out, &mut throttle, &mut rx, &tasks, &config, stop)
+            .await
+            .unwrap();
+        assert_eq!(tasks.active(), 1);
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "PRIVMSG #test :queued\r\n\
+             PRIVMSG #test :first\r\n\
+             PRIVMSG #test :second\r\n\
+             QUIT :see you\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn registered() {
+        use crate::{bot::*, test::YamlResponder, CachedConfig, Config};
+
+        let mut listener = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for welcome in &[true, false] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                if *welcome {
+                    socket
+                        .write_all(b":irc 001 noye :welcome\r\n")
+                        .await
+                        .unwrap();
+                }
+                // hang up, but keep reading so the client sees an eof rather than a reset
+                socket.shutdown(std::net::Shutdown::Write).unwrap();
+                let mut rest = vec![];
+                let _ = socket.read_to_end(&mut rest).await;
+            }
+        });
+
+        let mut state = State::default();
+        state.insert(CachedConfig::new(Config::default(), "noye.toml"));
+        let (tx, rx) = mpsc::channel(8);
+        let runner = Runner::new(
+            state,
+            Writer(tx),
+            CommandsMap::default(),
+            PassivesList::default(),
+            EventsMap::default(),
+        );
+        let mut supervisor = Supervisor::new(runner, YamlResponder::default(), rx);
+        let config = config::Irc::default();
+
+        for expected in &[true, false] {
+            let conn = Connection::Plain(tokio::net::TcpStream::connect(addr).await.unwrap());
+            let (registered, result) = supervisor.session(conn, &config).await;
+            assert!(matches!(result, Ok(Disconnect::Lost)));
+            assert_eq!(registered, *expected);
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let mut backoff = Backoff::new(&config::Reconnect {
+            min_delay: "5s".into(),
+            max_delay: "30s".into(),
+        });
+
+        let delays = std::iter::repeat_with(|| backoff.next().as_secs())
+            .take(6)
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![5, 10, 20, 30, 30, 30]);
+
+        backoff.reset();
+        assert_eq!(backoff.next().as_secs(), 5);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::config;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+};
+use tokio::time::{Duration, Instant};
+
+/// A token bucket for the outgoing lines
+///
+/// Lines are queued per target and the targets are taken in turn, so one busy channel can't starve
+/// the others. `PONG`s skip the queues entirely
+pub struct Throttle {
+    burst: u32,
+    refill: Duration,
+    tokens: u32,
+    last: Instant,
+
+    priority: VecDeque<String>,
+    order: VecDeque<String>,
+    queues: HashMap<String, VecDeque<String>>,
+}
+
+impl Throttle {
+    pub fn new(config: &config::Throttle) -> Self {
+        let refill = super::parse_duration(&config.refill, Duration::from_secs(2));
+        let burst = config.burst.max(1);
+        Self {
+            burst,
+            refill,
+            tokens: burst,
+            last: Instant::now(),
+
+            priority: Default::default(),
+            order: Default::default(),
+            queues: Default::default(),
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        let mut iter = line.split_whitespace();
+        let target = match iter.next() {
+            Some("PONG") => {
+                self.priority.push_back(line);
+                return;
+            }
+            Some("PRIVMSG") | Some("NOTICE") => iter.next().unwrap_or_default().to_string(),
+            // everything else shares a queue
+            _ => String::new(),
+        };
+
+        if !self.order.contains(&target) {
+            self.order.push_back(target.clone());
+        }
+        self.queues.entry(target).or_default().push_back(line);
+    }
+
+    /// Takes the next line that can be sent at `now`
+    pub fn pop(&mut self, now: Instant) -> Option<String> {
+        if let Some(line) = self.priority.pop_front() {
+            return Some(line);
+        }
+
+        self.refill(now);
+        if self.tokens == 0 {
+            return None;
+        }
+
+        let line = self.next_line()?;
+        self.tokens -= 1;
+        Some(line)
+    }
+
+    /// Takes everything that is queued, ignoring the rate limit. This is for when we're quitting
+    pub fn drain(&mut self) -> Vec<String> {
+        let mut lines = self.priority.drain(..).collect::<Vec<_>>();
+        lines.extend(std::iter::from_fn(|| self.next_line()));
+        lines
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        let target = self.order.pop_front()?;
+        let queue = self.queues.get_mut(&target)?;
+        let line = queue.pop_front()?;
+        if queue.is_empty() {
+            self.queues.remove(&target);
+        } else {
+            self.order.push_back(target);
+        }
+        Some(line)
+    }
+
+    /// When the next queued line can be sent, if there are any
+    pub fn next_ready(&self) -> Option<Instant> {
+        if !self.priority.is_empty() {
+            return Some(self.last);
+        }
+        if self.order.is_empty() {
+            return None;
+        }
+        match self.tokens {
+            0 => Some(self.last + self.refill),
+            _ => Some(self.last),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last);
+        let count = elapsed.as_millis() / self.refill.as_millis().max(1);
+        let count = u32::try_from(count).unwrap_or(u32::MAX);
+        if count == 0 {
+            return;
+        }
+
+        // this can be a lot of tokens after a long idle period
+        self.tokens = self.tokens.saturating_add(count).min(self.burst);
+        self.last = if self.tokens == self.burst {
+            now
+        } else {
+            self.last + self.refill * count
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> Throttle {
+        Throttle::new(&config::Throttle {
+            burst: 2,
+            refill: "1s".into(),
+        })
+    }
+
+    #[test]
+    fn burst_and_refill() {
+        let mut throttle = throttle();
+        let now = throttle.last;
+        for i in 0..4 {
+            throttle.push(format!("PRIVMSG #test :{}\r\n", i));
+        }
+
+        assert_eq!(throttle.pop(now).unwrap(), "PRIVMSG #test :0\r\n");
+        assert_eq!(throttle.pop(now).unwrap(), "PRIVMSG #test :1\r\n");
+        assert!(throttle.pop(now).is_none());
+        assert_eq!(throttle.next_ready(), Some(now + Duration::from_secs(1)));
+
+        let now = now + Duration::from_secs(1);
+        assert_eq!(throttle.pop(now).unwrap(), "PRIVMSG #test :2\r\n");
+        assert!(throttle.pop(now).is_none());
+
+        let now = now + Duration::from_secs(5);
+        assert_eq!(throttle.pop(now).unwrap(), "PRIVMSG #test :3\r\n");
+        assert!(throttle.pop(now).is_none());
+        assert!(throttle.next_ready().is_none());
+    }
+
+    #[test]
+    fn long_idle() {
+        let mut throttle = throttle();
+        throttle.refill = Duration::from_millis(1);
+        let now = throttle.last;
+        for i in 0..3 {
+            throttle.push(format!("PRIVMSG #test :{}\r\n", i));
+        }
+        assert!(throttle.pop(now).is_some());
+        assert!(throttle.pop(now).is_some());
+
+        // more milliseconds than fit in the tokens
+        let now = now + Duration::from_secs(60 * 60 * 24 * 60);
+        assert_eq!(throttle.pop(now).unwrap(), "PRIVMSG #test :2\r\n");
+        assert_eq!(throttle.tokens, 1);
+    }
+
+    #[test]
+    fn targets_take_turns() {
+        let mut throttle = Throttle::new(&config::Throttle {
+            burst: 10,
+            refill: "1s".into(),
+        });
+        let now = throttle.last;
+        for i in 0..3 {
+            throttle.push(format!("PRIVMSG #a :{}\r\n", i));
+        }
+        throttle.push("PRIVMSG #b :0\r\n".into());
+        throttle.push("JOIN #c\r\n".into());
+
+        let lines = std::iter::from_fn(|| throttle.pop(now)).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "PRIVMSG #a :0\r\n",
+                "PRIVMSG #b :0\r\n",
+                "JOIN #c\r\n",
+                "PRIVMSG #a :1\r\n",
+                "PRIVMSG #a :2\r\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn drain() {
+        let mut throttle = throttle();
+        let now = throttle.last;
+        for i in 0..3 {
+            throttle.push(format!("PRIVMSG #a :{}\r\n", i));
+        }
+        throttle.push("PRIVMSG #b :0\r\n".into());
+        assert_eq!(throttle.pop(now).unwrap(), "PRIVMSG #a :0\r\n");
+        throttle.push("PONG :irc.example.com\r\n".into());
+
+        assert_eq!(
+            throttle.drain(),
+            vec![
+                "PONG :irc.example.com\r\n",
+                "PRIVMSG #b :0\r\n",
+                "PRIVMSG #a :1\r\n",
+                "PRIVMSG #a :2\r\n",
+            ]
+        );
+        assert!(throttle.next_ready().is_none());
+    }
+
+    #[test]
+    fn pong_priority() {
+        let mut throttle = throttle();
+        let now = throttle.last;
+        for i in 0..3 {
+            throttle.push(format!("PRIVMSG #test :{}\r\n", i));
+        }
+        assert!(throttle.pop(now).is_some());
+        assert!(throttle.pop(now).is_some());
+
+        throttle.push("PONG :irc.example.com\r\n".into());
+        assert_eq!(throttle.next_ready(), Some(now));
+        assert_eq!(throttle.pop(now).unwrap(), "PONG :irc.example.com\r\n");
+        assert!(throttle.pop(now).is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::config;
+
+use anyhow::Context as _;
+use native_tls::{Certificate, Identity, TlsConnector};
+use tokio::net::TcpStream;
+
+pub(super) async fn connect(
+    stream: TcpStream,
+    address: &str,
+    config: &config::Tls,
+) -> anyhow::Result<tokio_tls::TlsStream<TcpStream>> {
+    let domain = match &config.domain {
+        Some(domain) => domain.as_str(),
+        None => address.rsplitn(2, ':').last().unwrap(),
+    };
+
+    let connector = tokio_tls::TlsConnector::from(connector(config).await?);
+    connector
+        .connect(domain, stream)
+        .await
+        .with_context(|| format!("cannot establish tls with '{}'", address))
+}
+
+async fn connector(config: &config::Tls) -> anyhow::Result<TlsConnector> {
+    let mut builder = TlsConnector::builder();
+
+    if let Some(ca_file) = &config.ca_file {
+        builder.add_root_certificate(load_certificate(ca_file).await?);
+    }
+
+    // only these are trusted, so the server's certificate has to be signed by one of them
+    if let Some(bundle) = &config.ca_bundle {
+        builder.disable_built_in_roots(true);
+        for cert in load_bundle(bundle).await? {
+            builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Some(client_cert) = &config.client_cert {
+        let data = tokio::fs::read(client_cert)
+            .await
+            .with_context(|| format!("cannot read client certificate '{}'", client_cert))?;
+        let pass = config.client_cert_pass.as_deref().unwrap_or_default();
+        let identity = Identity::from_pkcs12(&data, pass)
+            .with_context(|| format!("invalid client certificate '{}'", client_cert))?;
+        builder.identity(identity);
+    }
+
+    builder.build().map_err(Into::into)
+}
+
+async fn load_certificate(path: &str) -> anyhow::Result<Certificate> {
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("cannot read certificate '{}'", path))?;
+    Certificate::from_pem(&data)
+        .or_else(|_| Certificate::from_der(&data))
+        .with_context(|| format!("invalid certificate '{}'", path))
+}
+
+/// Loads every certificate in a PEM file, or the one in a DER file
+async fn load_bundle(path: &str) -> anyhow::Result<Vec<Certificate>> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("cannot read certificates '{}'", path))?;
+    let pem = match std::str::from_utf8(&data) {
+        Ok(pem) if pem.contains(END) => pem,
+        _ => {
+            let cert = Certificate::from_der(&data)
+                .with_context(|| format!("invalid certificate '{}'", path))?;
+            return Ok(vec![cert]);
+        }
+    };
+
+    let (mut certs, mut rest) = (vec![], pem);
+    while let Some(pos) = rest.find(END) {
+        let (part, tail) = rest.split_at(pos + END.len());
+        let cert = Certificate::from_pem(part.as_bytes())
+            .with_context(|| format!("invalid certificate #{} in '{}'", certs.len() + 1, path))?;
+        certs.push(cert);
+        rest = tail;
+    }
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ca_bundle() {
+        let certs = load_bundle("./snapshots/inputs/tls/bundle.pem")
+            .await
+            .unwrap();
+        assert_eq!(certs.len(), 2);
+        let certs = load_bundle("./snapshots/inputs/tls/one.der").await.unwrap();
+        assert_eq!(certs.len(), 1);
+        assert_eq!(
+            certs[0].to_der().unwrap(),
+            std::fs::read("./snapshots/inputs/tls/one.der").unwrap()
+        );
+        assert!(load_bundle("./Cargo.toml").await.is_err());
+
+        let config = config::Tls {
+            ca_bundle: Some("./snapshots/inputs/tls/bundle.pem".into()),
+            ..Default::default()
+        };
+        connector(&config).await.unwrap();
+    }
+
+    #[test]
+    fn config() {
+        let irc: config::Irc = toml::from_str(
+            r#"
+            address = "irc.example.com:6697"
+            name = "noye"
+            user = "noye"
+            real = "noye"
+            channels = []
+
+            [tls]
+            ca_bundle = "ca.pem"
+            "#,
+        )
+        .unwrap();
+        let tls = irc.tls.as_ref().unwrap();
+        assert_eq!(tls.ca_bundle.as_deref(), Some("ca.pem"));
+        assert!(tls.ca_file.is_none());
+        // nothing has to be set for auth
+        assert!(irc.auth.is_none());
+        assert!(matches!(irc.auth(), config::Auth::None));
+    }
+}
@@ -0,0 +1,572 @@
+//! The restart daemon, this is what `noye-supervisor` runs
+//!
+//! It runs the bot, and restarts it when `!restart` or `!respawn` ask it to or when it crashes.
+//! The output of a crash is kept so the next run can put it in its log
+use crate::{config, util::inspect_err, Shutdown};
+
+use anyhow::Context as _;
+use futures::prelude::*;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    process::{Child, Command},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// How long to wait before starting the bot again after a crash
+const CRASH_DELAY: Duration = Duration::from_secs(5);
+
+/// How long the bot has to quit by itself before it is killed
+const QUIT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How many lines of output are kept for a crash report
+const TAIL_LINES: usize = 100;
+
+/// What the daemon sends back once it has a request, older daemons don't send anything
+const ACK: &[u8] = b"OK\0";
+
+/// How long the bot waits for the daemon to acknowledge a request, before it carries on anyway
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the bot can ask the daemon to do, after which it quits
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Request {
+    /// Start it again straight away
+    Restart,
+    /// Start it again after this many seconds
+    Delay(u16),
+}
+
+impl Request {
+    pub fn encode(self) -> String {
+        match self {
+            Self::Restart => "RESTART\0".to_string(),
+            Self::Delay(delay) => format!("DELAY {}\0", delay),
+        }
+    }
+
+    pub fn parse(data: &str) -> anyhow::Result<Self> {
+        let data = data.trim_end_matches('\0');
+        let mut iter = data.splitn(2, ' ');
+        match (iter.next(), iter.next()) {
+            (Some("RESTART"), None) => Ok(Self::Restart),
+            (Some("DELAY"), Some(delay)) => delay
+                .parse()
+                .map(Self::Delay)
+                .with_context(|| format!("invalid delay: {}", delay)),
+            _ => anyhow::bail!("unknown request: {}", data.escape_debug()),
+        }
+    }
+
+    /// Sends this to the daemon listening at `addr`, waiting a little for it to be acknowledged
+    ///
+    /// The bot should wait for this before it quits, otherwise the daemon could see it exit
+    /// before it sees the request and think it stopped for good. The acknowledgement is optional,
+    /// so a daemon that doesn't send one only gets a warning
+    pub async fn send(self, addr: &str) -> anyhow::Result<()> {
+        let mut stream = TcpStream::connect(addr)
+            .await
+            .with_context(|| format!("cannot connect to restart daemon at '{}'", addr))?;
+        stream.write_all(self.encode().as_bytes()).await?;
+        stream.flush().await?;
+
+        let (mut ack, mut reader) = (vec![], BufReader::new(&mut stream).take(8));
+        let read = reader.read_until(b'\0', &mut ack);
+        match tokio::time::timeout(ACK_TIMEOUT, read).await {
+            Ok(Ok(..)) if ack == ACK => {}
+            Ok(Err(err)) => log::warn!("cannot read the restart daemon's reply: {}", err),
+            _ => log::warn!("the restart daemon at '{}' didn't acknowledge us", addr),
+        }
+        Ok(())
+    }
+}
+
+/// Runs the bot until it quits by itself, or `shutdown` is stopped
+pub async fn run(config: config::Restart, shutdown: Shutdown) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&config.address)
+        .await
+        .with_context(|| format!("cannot listen on '{}'", config.address))?;
+    log::info!("listening for requests on {}", config.address);
+
+    let (tx, mut requests) = mpsc::channel(8);
+    tokio::spawn(async move {
+        if let Err(err) = accept(listener, tx).await {
+            inspect_err(&err, || "accepting requests");
+        }
+    });
+
+    loop {
+        let (status, output, request) = match supervise(&config, &shutdown, &mut requests).await {
+            Ok(exited) => exited,
+            Err(err) => {
+                inspect_err(&err, || "starting the bot");
+                (None, vec![], None)
+            }
+        };
+        if shutdown.stopping().is_some() {
+            break Ok(());
+        }
+
+        let delay = match (request, status) {
+            (Some(request), _) => {
+                rebuild(&config).await;
+                match request {
+                    Request::Restart => Duration::from_secs(0),
+                    Request::Delay(delay) => Duration::from_secs(delay.into()),
+                }
+            }
+            (None, Some(status)) if status.success() => {
+                log::info!("the bot quit by itself, stopping");
+                break Ok(());
+            }
+            (None, status) => {
+                let status = status.map(|s| s.to_string());
+                let status = status.as_deref().unwrap_or("cannot be started");
+                log::error!("the bot crashed: {}", status);
+                if let Err(err) = write_crash_report(&config.crash_log, status, &output) {
+                    inspect_err(&err, || "writing the crash report");
+                }
+                CRASH_DELAY
+            }
+        };
+
+        log::info!("starting the bot in {} seconds", delay.as_secs());
+        tokio::select! {
+            _ = tokio::time::delay_for(delay) => {}
+            _ = shutdown.wait() => break Ok(()),
+        }
+    }
+}
+
+/// Runs the bot once, returning how it exited, its last lines of output and what it asked for
+async fn supervise(
+    config: &config::Restart,
+    shutdown: &Shutdown,
+    requests: &mut mpsc::Receiver<Request>,
+) -> anyhow::Result<(Option<ExitStatus>, Vec<String>, Option<Request>)> {
+    let (program, args) = command(config)?;
+    log::info!("starting {}", program.display());
+    let mut child = Command::new(&program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("cannot start {}", program.display()))?;
+
+    let tail = Arc::new(Mutex::new(Tail::default()));
+    let readers = vec![
+        capture(child.stdout.take(), tail.clone(), false),
+        capture(child.stderr.take(), tail.clone(), true),
+    ];
+
+    let (mut request, mut deadline) = (None, None);
+    let status = loop {
+        tokio::select! {
+            status = &mut child => break status?,
+            Some(req) = requests.recv() => {
+                log::info!("the bot asked for {:?}", req);
+                request.replace(req);
+                deadline.get_or_insert_with(|| Instant::now() + QUIT_TIMEOUT);
+            }
+            _ = shutdown.wait(), if deadline.is_none() => {
+                log::info!("stopping, asking the bot to quit");
+                terminate(&child);
+                deadline.replace(Instant::now() + QUIT_TIMEOUT);
+            }
+            _ = tokio::time::delay_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                log::warn!("the bot didn't quit, killing it");
+                kill(&mut child);
+                deadline.replace(Instant::now() + QUIT_TIMEOUT);
+            }
+        }
+    };
+
+    // it could have quit before we saw what it asked for
+    while let Ok(req) = requests.try_recv() {
+        request.replace(req);
+    }
+
+    // the pipes are closed once it has exited
+    future::join_all(readers).await;
+    let output = tail.lock().unwrap().lines.drain(..).collect();
+    Ok((Some(status), output, request))
+}
+
+/// Asks the bot to quit, it is killed if it hasn't by the deadline
+#[cfg(unix)]
+fn terminate(child: &Child) {
+    // the bot shuts down cleanly on SIGTERM
+    if unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) } != 0 {
+        log::warn!(
+            "cannot terminate the bot: {}",
+            std::io::Error::last_os_error()
+        )
+    }
+}
+
+/// There isn't a SIGTERM here, so the bot is only killed once the deadline passes
+#[cfg(not(unix))]
+fn terminate(_: &Child) {}
+
+fn kill(child: &mut Child) {
+    if let Err(err) = child.kill() {
+        log::warn!("cannot kill the bot: {}", err)
+    }
+}
+
+/// The program and its arguments, the `noye` next to us is used if there isn't a command
+fn command(config: &config::Restart) -> anyhow::Result<(PathBuf, Vec<String>)> {
+    let mut iter = config.command.iter().flat_map(|s| s.split_whitespace());
+    let program = match iter.next() {
+        Some(program) => program.into(),
+        None => {
+            std::env::current_exe()?.with_file_name(format!("noye{}", std::env::consts::EXE_SUFFIX))
+        }
+    };
+    Ok((program, iter.map(ToString::to_string).collect()))
+}
+
+/// Runs the rebuild command if there is one, the old bot is used if it fails
+async fn rebuild(config: &config::Restart) {
+    let mut iter = match &config.rebuild {
+        Some(rebuild) => rebuild.split_whitespace(),
+        None => return,
+    };
+    let program = match iter.next() {
+        Some(program) => program,
+        None => return,
+    };
+
+    log::info!(
+        "rebuilding with: {}",
+        config.rebuild.as_deref().unwrap_or_default()
+    );
+    match Command::new(program).args(iter).status().await {
+        Ok(status) if status.success() => log::info!("rebuilt the bot"),
+        Ok(status) => log::warn!("cannot rebuild the bot: {}", status),
+        Err(err) => log::warn!("cannot rebuild the bot: {}", err),
+    }
+}
+
+/// Copies the lines from `pipe` to our own output, keeping the last few of them
+fn capture(
+    pipe: Option<impl AsyncRead + Unpin + Send + 'static>,
+    tail: Arc<Mutex<Tail>>,
+    stderr: bool,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut lines = match pipe {
+            Some(pipe) => BufReader::new(pipe).lines(),
+            None => return,
+        };
+        while let Some(Ok(line)) = lines.next().await {
+            if stderr {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+            tail.lock().unwrap().push(line);
+        }
+    })
+}
+
+#[derive(Default)]
+struct Tail {
+    lines: VecDeque<String>,
+}
+
+impl Tail {
+    fn push(&mut self, line: String) {
+        if self.lines.len() == TAIL_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+async fn accept(mut listener: TcpListener, tx: mpsc::Sender<Request>) -> anyhow::Result<()> {
+    loop {
+        let (mut stream, addr) = listener.accept().await?;
+        let mut tx = tx.clone();
+        tokio::spawn(async move {
+            match read_request(&mut stream).await {
+                // only acknowledged once the supervisor will see it
+                Ok(request) if tx.send(request).await.is_ok() => {
+                    if let Err(err) = stream.write_all(ACK).await {
+                        log::warn!("cannot acknowledge the request from {}: {}", addr, err)
+                    }
+                }
+                Ok(..) => {}
+                Err(err) => log::warn!("invalid request from {}: {:#}", addr, err),
+            }
+        });
+    }
+}
+
+async fn read_request(stream: impl AsyncRead + Unpin) -> anyhow::Result<Request> {
+    let mut data = vec![];
+    BufReader::new(stream)
+        .take(64)
+        .read_until(b'\0', &mut data)
+        .await?;
+    Request::parse(std::str::from_utf8(&data)?)
+}
+
+fn write_crash_report(path: &str, status: &str, output: &[String]) -> anyhow::Result<()> {
+    let mut report = format!("the bot crashed: {}\n", status);
+    for line in output {
+        report.push_str(line);
+        report.push('\n');
+    }
+    std::fs::write(path, report).with_context(|| format!("cannot write {}", path))
+}
+
+/// Logs the report from the last crash, if there was one. The report is removed afterwards
+pub fn report_crash(path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let report = match std::fs::read_to_string(path) {
+        Ok(report) => report,
+        Err(..) => return,
+    };
+
+    log::error!("the last run crashed, its output was:");
+    for line in report.lines() {
+        log::error!("> {}", line);
+    }
+    if let Err(err) = std::fs::remove_file(path) {
+        log::warn!("cannot remove {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        for request in &[Request::Restart, Request::Delay(15)] {
+            assert_eq!(Request::parse(&request.encode()).unwrap(), *request);
+        }
+        assert_eq!(Request::Delay(30).encode(), "DELAY 30\0");
+
+        for data in &[
+            "RESTART now\0",
+            "DELAY\0",
+            "DELAY soon\0",
+            "DELAY 70000\0",
+            "STOP\0",
+        ] {
+            assert!(Request::parse(data).is_err(), "{}", data.escape_debug());
+        }
+    }
+
+    #[tokio::test]
+    async fn receive_requests() {
+        let listener = TcpListener::bind("localhost:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (tx, mut rx) = mpsc::channel(8);
+        tokio::spawn(accept(listener, tx));
+
+        Request::Delay(3).send(&addr).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), Request::Delay(3));
+
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        stream.write_all(b"GARBAGE\0").await.unwrap();
+        drop(stream);
+
+        Request::Restart.send(&addr).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), Request::Restart);
+    }
+
+    #[tokio::test]
+    async fn without_ack() {
+        // a daemon that reads the request, but doesn't acknowledge it
+        let mut listener = TcpListener::bind("localhost:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let daemon = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            read_request(stream).await.unwrap()
+        });
+
+        Request::Delay(5).send(&addr).await.unwrap();
+        assert_eq!(daemon.await.unwrap(), Request::Delay(5));
+    }
+
+    #[test]
+    fn tail() {
+        let mut tail = Tail::default();
+        for i in 0..TAIL_LINES + 2 {
+            tail.push(i.to_string());
+        }
+        assert_eq!(tail.lines.len(), TAIL_LINES);
+        assert_eq!(tail.lines.front().unwrap(), "2");
+    }
+
+    #[test]
+    fn crash_report() {
+        let path = std::env::temp_dir().join(format!("noye-crash-{}.log", std::process::id()));
+        let output = vec!["thread 'main' panicked".to_string()];
+        write_crash_report(path.to_str().unwrap(), "exit code: 101", &output).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "the bot crashed: exit code: 101\nthread 'main' panicked\n"
+        );
+
+        report_crash(&path);
+        assert!(!path.exists());
+        // and there's nothing to report the next time
+        report_crash(&path);
+    }
+
+    /// A directory with a script standing in for the bot, and a config that runs it
+    #[cfg(unix)]
+    fn stub_bot(name: &str, script: &str) -> (PathBuf, config::Restart) {
+        let dir = std::env::temp_dir().join(format!("noye-daemon-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("bot.sh"),
+            format!("cd {}\n{}", dir.display(), script),
+        )
+        .unwrap();
+
+        // a free port for the daemon to listen on
+        let addr = std::net::TcpListener::bind("localhost:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config = config::Restart {
+            address: addr.to_string(),
+            command: Some(format!("sh {}", dir.join("bot.sh").display())),
+            rebuild: Some(format!("touch {}", dir.join("rebuilt").display())),
+            crash_log: dir.join("crash.log").to_str().unwrap().to_string(),
+        };
+        (dir, config)
+    }
+
+    /// Counts the runs, the first one waits for `go` before quitting
+    #[cfg(unix)]
+    const COUNT_RUNS: &str = r#"
+        n=$(cat runs 2>/dev/null || echo 0)
+        echo $((n + 1)) > runs
+        if [ "$n" = 0 ]; then
+            while [ ! -e go ]; do sleep 0.01; done
+        fi
+        "#;
+
+    #[cfg(unix)]
+    async fn wait_for(path: &Path) {
+        while !path.exists() {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[cfg(unix)]
+    async fn quit_after(dir: &Path, config: &config::Restart, request: Request) {
+        wait_for(&dir.join("runs")).await;
+        request.send(&config.address).await.unwrap();
+        std::fs::write(dir.join("go"), "").unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn restart() {
+        let (dir, config) = stub_bot("restart", COUNT_RUNS);
+        let shutdown = Shutdown::default();
+
+        let daemon = tokio::spawn(run(config.clone(), shutdown));
+        quit_after(&dir, &config, Request::Restart).await;
+        // the second run quits by itself, so the daemon stops
+        daemon.await.unwrap().unwrap();
+
+        assert_eq!(std::fs::read_to_string(dir.join("runs")).unwrap(), "2\n");
+        assert!(dir.join("rebuilt").exists());
+        assert!(!dir.join("crash.log").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn delay() {
+        let (dir, config) = stub_bot("delay", COUNT_RUNS);
+        let shutdown = Shutdown::default();
+
+        let daemon = tokio::spawn(run(config.clone(), shutdown));
+        quit_after(&dir, &config, Request::Delay(1)).await;
+        let start = Instant::now();
+        daemon.await.unwrap().unwrap();
+
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(std::fs::read_to_string(dir.join("runs")).unwrap(), "2\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn crash() {
+        let (dir, config) = stub_bot("crash", "echo runs >> runs\necho 'oh no' >&2\nexit 3");
+        let shutdown = Shutdown::default();
+
+        let daemon = tokio::spawn(run(config.clone(), shutdown.clone()));
+        wait_for(&dir.join("crash.log")).await;
+        // it would be started again after a while, but not if we're stopping
+        shutdown.stop(None);
+        daemon.await.unwrap().unwrap();
+
+        let report = std::fs::read_to_string(&config.crash_log).unwrap();
+        assert!(report.starts_with("the bot crashed: "), "{}", report);
+        assert!(report.ends_with(": 3\noh no\n"), "{}", report);
+        assert_eq!(std::fs::read_to_string(dir.join("runs")).unwrap(), "runs\n");
+        assert!(!dir.join("rebuilt").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn clean_exit() {
+        let (dir, config) = stub_bot("clean", "echo bye");
+        let (_tx, mut requests) = mpsc::channel(8);
+
+        let (status, output, request) = supervise(&config, &Shutdown::default(), &mut requests)
+            .await
+            .unwrap();
+        assert!(status.unwrap().success());
+        assert_eq!(output, vec!["bye"]);
+        assert!(request.is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn terminate_on_shutdown() {
+        let (dir, config) = stub_bot(
+            "terminate",
+            "trap 'echo terminated; exit 0' TERM\ntouch ready\nwhile true; do sleep 0.01; done",
+        );
+        let shutdown = Shutdown::default();
+        let (_tx, mut requests) = mpsc::channel(8);
+
+        let stop = {
+            let (ready, shutdown) = (dir.join("ready"), shutdown.clone());
+            async move {
+                wait_for(&ready).await;
+                shutdown.stop(None);
+            }
+        };
+        let (exited, _) = future::join(supervise(&config, &shutdown, &mut requests), stop).await;
+        let (status, output, _) = exited.unwrap();
+
+        // it was asked to quit rather than being killed
+        assert!(status.unwrap().success());
+        assert_eq!(output, vec!["terminated"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
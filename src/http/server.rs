@@ -21,18 +21,12 @@ impl Default for TempStore {
 }
 
 impl TempStore {
-    pub fn start_culling(&self) {
-        let inner = self.inner.clone();
-        tokio::spawn(async move {
-            use futures::prelude::*;
-            let mut tick = tokio::time::interval(tokio::time::Duration::from_secs(60));
-            while let Some(..) = tick.next().await {
-                let n = inner.write().await.cull();
-                if n > 0 {
-                    log::debug!("culled {} items", n);
-                }
-            }
-        });
+    /// Removes the items that are too old, this is run by the scheduler
+    pub async fn cull(&self) {
+        let n = self.inner.write().await.cull();
+        if n > 0 {
+            log::debug!("culled {} items", n);
+        }
     }
 }
 
@@ -2,12 +2,20 @@
 pub enum Command {
     Privmsg,
     Ping,
+    Pong,
     Ready,
     Quit,
     Join,
     Part,
+    Kick,
+    Mode,
+    Topic,
+    Notice,
+    Error,
     Invite,
     Nick,
+    Cap,
+    Authenticate,
     NickCollision,
     Numeric(u16),
     Unknown(Box<str>),
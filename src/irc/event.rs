@@ -0,0 +1,505 @@
+use super::{numeric::*, Command, RawMessage};
+
+/// A typed view of a `RawMessage`
+///
+/// Messages that are missing required parameters are an error, rather than a panic
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Privmsg {
+        nick: String,
+        target: String,
+        data: String,
+    },
+    Notice {
+        source: Option<String>,
+        target: String,
+        data: String,
+    },
+    Ctcp {
+        nick: String,
+        target: String,
+        command: String,
+        args: Option<String>,
+    },
+    CtcpReply {
+        nick: String,
+        target: String,
+        command: String,
+        args: Option<String>,
+    },
+    Join {
+        nick: String,
+        channel: String,
+    },
+    Part {
+        nick: String,
+        channel: String,
+        reason: Option<String>,
+    },
+    Kick {
+        by: String,
+        channel: String,
+        nick: String,
+        reason: Option<String>,
+    },
+    Quit {
+        nick: String,
+        reason: Option<String>,
+    },
+    Nick {
+        old: String,
+        new: String,
+    },
+    Mode {
+        source: Option<String>,
+        target: String,
+        modes: String,
+        args: Vec<String>,
+    },
+    Topic {
+        nick: String,
+        channel: String,
+        topic: Option<String>,
+    },
+    Invite {
+        nick: String,
+        channel: String,
+    },
+    Ping {
+        token: String,
+    },
+    Pong {
+        token: String,
+    },
+    Error {
+        message: String,
+    },
+    /// RPL_TOPIC
+    TopicReply {
+        channel: String,
+        topic: String,
+    },
+    /// RPL_TOPICWHOTIME
+    TopicWhoTime {
+        channel: String,
+        by: String,
+        at: u64,
+    },
+    /// RPL_NAMREPLY, the names keep their status prefixes (e.g. `@` and `+`)
+    Names {
+        channel: String,
+        names: Vec<String>,
+    },
+    /// RPL_ENDOFNAMES
+    EndOfNames {
+        channel: String,
+    },
+    /// A numeric that doesn't have its own event
+    Numeric {
+        code: u16,
+        params: Vec<String>,
+    },
+    Other,
+}
+
+/// The kinds of `Event`, handlers are registered for one of these
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Privmsg,
+    Notice,
+    Ctcp,
+    CtcpReply,
+    Join,
+    Part,
+    Kick,
+    Quit,
+    Nick,
+    Mode,
+    Topic,
+    Invite,
+    Ping,
+    Pong,
+    Error,
+    TopicReply,
+    TopicWhoTime,
+    Names,
+    EndOfNames,
+    Numeric(u16),
+    Other,
+}
+
+impl Event {
+    pub fn from_raw(msg: &RawMessage) -> anyhow::Result<Self> {
+        let param = |n| msg.param(n).map(ToString::to_string);
+        let expect = |n| {
+            param(n)
+                .ok_or_else(|| anyhow::anyhow!("{:?} is missing parameter #{}", msg.command, n + 1))
+        };
+
+        let event = match msg.command {
+            Command::Privmsg | Command::Notice => {
+                let (target, data) = (expect(0)?, expect(1)?);
+                match (parse_ctcp(&data), &msg.command) {
+                    (Some((command, args)), Command::Privmsg) => Self::Ctcp {
+                        nick: msg.expect_nick()?.to_string(),
+                        target,
+                        command,
+                        args,
+                    },
+                    (Some((command, args)), _) => Self::CtcpReply {
+                        nick: msg.expect_nick()?.to_string(),
+                        target,
+                        command,
+                        args,
+                    },
+                    (None, Command::Privmsg) => Self::Privmsg {
+                        nick: msg.expect_nick()?.to_string(),
+                        target,
+                        data,
+                    },
+                    (None, _) => Self::Notice {
+                        source: msg.source().map(ToString::to_string),
+                        target,
+                        data,
+                    },
+                }
+            }
+
+            Command::Join => Self::Join {
+                nick: msg.expect_nick()?.to_string(),
+                channel: expect(0)?,
+            },
+            Command::Part => Self::Part {
+                nick: msg.expect_nick()?.to_string(),
+                channel: expect(0)?,
+                reason: param(1),
+            },
+            Command::Kick => Self::Kick {
+                by: msg.source().unwrap_or_default().to_string(),
+                channel: expect(0)?,
+                nick: expect(1)?,
+                reason: param(2),
+            },
+            Command::Quit => Self::Quit {
+                nick: msg.expect_nick()?.to_string(),
+                reason: param(0),
+            },
+            Command::Nick => Self::Nick {
+                old: msg.expect_nick()?.to_string(),
+                new: expect(0)?,
+            },
+            Command::Mode => Self::Mode {
+                source: msg.source().map(ToString::to_string),
+                target: expect(0)?,
+                modes: expect(1)?,
+                args: msg.params().skip(2).map(ToString::to_string).collect(),
+            },
+            Command::Topic => Self::Topic {
+                nick: msg.expect_nick()?.to_string(),
+                channel: expect(0)?,
+                topic: param(1).filter(|s| !s.is_empty()),
+            },
+            Command::Invite => Self::Invite {
+                nick: msg.expect_nick()?.to_string(),
+                channel: expect(1)?,
+            },
+            Command::Ping => Self::Ping { token: expect(0)? },
+            Command::Pong => Self::Pong {
+                // servers send back `PONG <server> :<token>`
+                token: msg.params().last().unwrap_or_default().to_string(),
+            },
+            Command::Error => Self::Error {
+                message: param(0).unwrap_or_default(),
+            },
+
+            Command::Numeric(RPL_TOPIC) => Self::TopicReply {
+                channel: expect(1)?,
+                topic: param(2).unwrap_or_default(),
+            },
+            Command::Numeric(RPL_TOPICWHOTIME) => Self::TopicWhoTime {
+                channel: expect(1)?,
+                by: expect(2)?,
+                at: expect(3)?.parse()?,
+            },
+            // <client> <symbol> <channel> :<names>
+            Command::Numeric(RPL_NAMREPLY) => Self::Names {
+                channel: expect(2)?,
+                names: param(3)
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(ToString::to_string)
+                    .collect(),
+            },
+            Command::Numeric(RPL_ENDOFNAMES) => Self::EndOfNames {
+                channel: expect(1)?,
+            },
+
+            Command::Numeric(code) => Self::Numeric {
+                code,
+                params: msg.params().map(ToString::to_string).collect(),
+            },
+
+            _ => Self::Other,
+        };
+        Ok(event)
+    }
+
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::Privmsg { .. } => EventKind::Privmsg,
+            Self::Notice { .. } => EventKind::Notice,
+            Self::Ctcp { .. } => EventKind::Ctcp,
+            Self::CtcpReply { .. } => EventKind::CtcpReply,
+            Self::Join { .. } => EventKind::Join,
+            Self::Part { .. } => EventKind::Part,
+            Self::Kick { .. } => EventKind::Kick,
+            Self::Quit { .. } => EventKind::Quit,
+            Self::Nick { .. } => EventKind::Nick,
+            Self::Mode { .. } => EventKind::Mode,
+            Self::Topic { .. } => EventKind::Topic,
+            Self::Invite { .. } => EventKind::Invite,
+            Self::Ping { .. } => EventKind::Ping,
+            Self::Pong { .. } => EventKind::Pong,
+            Self::Error { .. } => EventKind::Error,
+            Self::TopicReply { .. } => EventKind::TopicReply,
+            Self::TopicWhoTime { .. } => EventKind::TopicWhoTime,
+            Self::Names { .. } => EventKind::Names,
+            Self::EndOfNames { .. } => EventKind::EndOfNames,
+            Self::Numeric { code, .. } => EventKind::Numeric(*code),
+            Self::Other => EventKind::Other,
+        }
+    }
+
+    /// The channel this happened in, if it happened in one
+    pub fn channel(&self) -> Option<&str> {
+        let channel = match self {
+            Self::Privmsg { target, .. }
+            | Self::Notice { target, .. }
+            | Self::Ctcp { target, .. }
+            | Self::CtcpReply { target, .. }
+            | Self::Mode { target, .. } => target,
+            Self::Join { channel, .. }
+            | Self::Part { channel, .. }
+            | Self::Kick { channel, .. }
+            | Self::Topic { channel, .. }
+            | Self::Invite { channel, .. }
+            | Self::TopicReply { channel, .. }
+            | Self::TopicWhoTime { channel, .. }
+            | Self::Names { channel, .. }
+            | Self::EndOfNames { channel } => channel,
+            _ => return None,
+        };
+        Some(channel.as_str()).filter(|s| s.starts_with(super::message::CHANNEL_TYPES))
+    }
+
+    /// Who this happened to, e.g. who joined or who was kicked
+    pub fn nick(&self) -> Option<&str> {
+        match self {
+            Self::Privmsg { nick, .. }
+            | Self::Ctcp { nick, .. }
+            | Self::CtcpReply { nick, .. }
+            | Self::Join { nick, .. }
+            | Self::Part { nick, .. }
+            | Self::Kick { nick, .. }
+            | Self::Quit { nick, .. }
+            | Self::Topic { nick, .. }
+            | Self::Invite { nick, .. } => Some(nick),
+            Self::Nick { new, .. } => Some(new),
+            _ => None,
+        }
+    }
+}
+
+fn parse_ctcp(data: &str) -> Option<(String, Option<String>)> {
+    const DELIM: char = '\x01';
+    if !data.starts_with(DELIM) {
+        return None;
+    }
+
+    // the trailing delimiter is optional
+    let data = data.trim_matches(DELIM);
+    let mut iter = data.splitn(2, ' ');
+    let command = iter.next().filter(|s| !s.is_empty())?.to_uppercase();
+    let args = iter.next().map(ToString::to_string);
+    Some((command, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(input: &str) -> Event {
+        Event::from_raw(&RawMessage::parse(input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn privmsg_and_ctcp() {
+        assert_eq!(
+            event(":museun!~m@localhost PRIVMSG #test :hello world\r\n"),
+            Event::Privmsg {
+                nick: "museun".into(),
+                target: "#test".into(),
+                data: "hello world".into(),
+            }
+        );
+
+        assert_eq!(
+            event(":museun!~m@localhost PRIVMSG #test :\x01ACTION waves\x01\r\n"),
+            Event::Ctcp {
+                nick: "museun".into(),
+                target: "#test".into(),
+                command: "ACTION".into(),
+                args: Some("waves".into()),
+            }
+        );
+
+        assert_eq!(
+            event(":museun!~m@localhost NOTICE noye :\x01VERSION\x01\r\n"),
+            Event::CtcpReply {
+                nick: "museun".into(),
+                target: "noye".into(),
+                command: "VERSION".into(),
+                args: None,
+            }
+        );
+
+        assert_eq!(
+            event(":irc.example.com NOTICE * :*** Looking up your hostname\r\n"),
+            Event::Notice {
+                source: Some("irc.example.com".into()),
+                target: "*".into(),
+                data: "*** Looking up your hostname".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn channel_management() {
+        assert_eq!(
+            event(":museun!~m@localhost KICK #test noye :bye\r\n"),
+            Event::Kick {
+                by: "museun".into(),
+                channel: "#test".into(),
+                nick: "noye".into(),
+                reason: Some("bye".into()),
+            }
+        );
+
+        assert_eq!(
+            event(":museun!~m@localhost MODE #test +ov noye museun\r\n"),
+            Event::Mode {
+                source: Some("museun".into()),
+                target: "#test".into(),
+                modes: "+ov".into(),
+                args: vec!["noye".into(), "museun".into()],
+            }
+        );
+
+        assert_eq!(
+            event(":museun!~m@localhost TOPIC #test :a new topic\r\n"),
+            Event::Topic {
+                nick: "museun".into(),
+                channel: "#test".into(),
+                topic: Some("a new topic".into()),
+            }
+        );
+
+        assert_eq!(
+            event(":museun!~m@localhost JOIN :#test\r\n"),
+            Event::Join {
+                nick: "museun".into(),
+                channel: "#test".into(),
+            }
+        );
+
+        assert_eq!(
+            event(":museun!~m@localhost NICK :m\r\n"),
+            Event::Nick {
+                old: "museun".into(),
+                new: "m".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(
+            event(":irc.example.com 353 noye = #test :@museun +noye other\r\n"),
+            Event::Names {
+                channel: "#test".into(),
+                names: vec!["@museun".into(), "+noye".into(), "other".into()],
+            }
+        );
+
+        assert_eq!(
+            event(":irc.example.com 366 noye #test :End of /NAMES list.\r\n"),
+            Event::EndOfNames {
+                channel: "#test".into(),
+            }
+        );
+
+        assert_eq!(
+            event(":irc.example.com 332 noye #test :the topic\r\n"),
+            Event::TopicReply {
+                channel: "#test".into(),
+                topic: "the topic".into(),
+            }
+        );
+
+        assert_eq!(
+            event(":irc.example.com 333 noye #test museun 1591014645\r\n"),
+            Event::TopicWhoTime {
+                channel: "#test".into(),
+                by: "museun".into(),
+                at: 1_591_014_645,
+            }
+        );
+
+        assert_eq!(
+            event("ERROR :Closing Link: localhost (Ping timeout)\r\n"),
+            Event::Error {
+                message: "Closing Link: localhost (Ping timeout)".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn kinds() {
+        let kick = event(":museun!~m@localhost KICK #test noye :bye\r\n");
+        assert_eq!(kick.kind(), EventKind::Kick);
+        assert_eq!(kick.channel(), Some("#test"));
+        assert_eq!(kick.nick(), Some("noye"));
+
+        let query = event(":museun!~m@localhost PRIVMSG noye :hello\r\n");
+        assert_eq!(query.channel(), None);
+        assert_eq!(query.nick(), Some("museun"));
+
+        let numeric = event(":irc.example.com 396 noye example.com :is now your hidden host\r\n");
+        assert_eq!(numeric.kind(), EventKind::Numeric(396));
+        assert_eq!(
+            numeric,
+            Event::Numeric {
+                code: 396,
+                params: vec![
+                    "noye".into(),
+                    "example.com".into(),
+                    "is now your hidden host".into()
+                ],
+            }
+        );
+        assert_eq!(numeric.channel(), None);
+    }
+
+    #[test]
+    fn missing_params() {
+        for input in &[
+            ":museun!~m@localhost KICK #test\r\n",
+            ":irc.example.com PRIVMSG #test :hello\r\n",
+            ":irc.example.com 353 noye =\r\n",
+            ":museun!~m@localhost MODE #test\r\n",
+        ] {
+            let msg = RawMessage::parse(input).unwrap();
+            assert!(Event::from_raw(&msg).is_err(), "{}", input.escape_debug());
+        }
+    }
+}
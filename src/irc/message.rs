@@ -1,6 +1,31 @@
+use super::Tags;
+
 #[derive(Clone, Debug)]
 pub struct Message {
     pub sender: String,
+    /// The sender's `nick!user@host`
+    pub hostmask: Option<String>,
+    /// Where the message was sent, for a query this is our nick
     pub channel: String,
     pub data: String,
+    pub tags: Tags,
+}
+
+// TODO get this from RPL_ISUPPORT (CHANTYPES)
+pub(crate) const CHANNEL_TYPES: &[char] = &['#', '&', '+', '!'];
+
+impl Message {
+    /// Whether this was sent directly to us, rather than to a channel
+    pub fn is_query(&self) -> bool {
+        !self.channel.starts_with(CHANNEL_TYPES)
+    }
+
+    /// Where responses should go: the channel, or the sender for a query
+    pub fn reply_target(&self) -> &str {
+        if self.is_query() {
+            &self.sender
+        } else {
+            &self.channel
+        }
+    }
 }
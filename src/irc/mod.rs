@@ -1,10 +1,18 @@
 mod command;
+mod event;
 mod message;
+pub mod numeric;
 mod parser;
 mod prefix;
 mod raw;
+mod split;
+mod tags;
 
 pub use command::Command;
+pub use event::{Event, EventKind};
 pub use message::Message;
+pub(crate) use message::CHANNEL_TYPES;
 pub use prefix::Prefix;
 pub use raw::RawMessage;
+pub use split::{split, MAX_LINE};
+pub use tags::Tags;
@@ -0,0 +1,13 @@
+//! Names for the numeric replies we care about
+
+pub const RPL_TOPIC: u16 = 332;
+pub const RPL_TOPICWHOTIME: u16 = 333;
+pub const RPL_NAMREPLY: u16 = 353;
+pub const RPL_ENDOFNAMES: u16 = 366;
+pub const RPL_HOSTHIDDEN: u16 = 396;
+
+pub const RPL_LOGGEDIN: u16 = 900;
+pub const ERR_NICKLOCKED: u16 = 902;
+pub const RPL_SASLSUCCESS: u16 = 903;
+pub const ERR_SASLFAIL: u16 = 904;
+pub const ERR_SASLABORTED: u16 = 906;
@@ -1,4 +1,4 @@
-use super::{Command, Prefix};
+use super::{Command, Prefix, Tags};
 use anyhow::Context as _;
 
 pub struct Parser<'a> {
@@ -11,6 +11,17 @@ impl<'a> Parser<'a> {
         Self { input, pos: 0 }
     }
 
+    pub fn tags(&mut self) -> Tags {
+        let input = &self.input[self.pos..];
+        if input.starts_with('@') {
+            let pos = input.find(' ').unwrap_or_else(|| input.len());
+            // a line with only tags has no separator to skip
+            self.pos += (pos + 1).min(input.len());
+            return Tags::parse(&input[..pos]);
+        }
+        Tags::default()
+    }
+
     pub fn prefix(&mut self) -> Option<Prefix> {
         let input = &self.input[self.pos..];
         if input.starts_with(':') {
@@ -33,12 +44,20 @@ impl<'a> Parser<'a> {
             "433" => Command::NickCollision,
             "001" => Command::Ready,
             "PING" => Command::Ping,
+            "PONG" => Command::Pong,
             "INVITE" => Command::Invite,
             "JOIN" => Command::Join,
             "PART" => Command::Part,
+            "KICK" => Command::Kick,
+            "MODE" => Command::Mode,
+            "TOPIC" => Command::Topic,
+            "NOTICE" => Command::Notice,
+            "ERROR" => Command::Error,
             "QUIT" => Command::Quit,
             "NICK" => Command::Nick,
             "PRIVMSG" => Command::Privmsg,
+            "CAP" => Command::Cap,
+            "AUTHENTICATE" => Command::Authenticate,
             s => s
                 .parse::<u16>()
                 .map(Command::Numeric)
@@ -49,7 +68,15 @@ impl<'a> Parser<'a> {
 
     pub fn args(&mut self) -> Vec<String> {
         let input = &self.input[self.pos..];
-        let pos = input.find(':').unwrap_or_else(|| input.len());
+        // only a ':' at the start of a parameter begins the trailing part
+        let pos = if input.starts_with(':') {
+            0
+        } else {
+            input
+                .find(" :")
+                .map(|pos| pos + 1)
+                .unwrap_or_else(|| input.len())
+        };
         self.pos += pos + 1;
         input[..pos]
             .split_whitespace()
@@ -1,7 +1,8 @@
-use super::{parser::Parser, Command, Message, Prefix};
+use super::{parser::Parser, Command, Message, Prefix, Tags};
 
 #[derive(Debug)]
 pub struct RawMessage {
+    pub tags: Tags,
     pub prefix: Option<Prefix>,
     pub command: Command,
     pub args: Vec<String>,
@@ -21,6 +22,7 @@ impl RawMessage {
 
         let mut parser = Parser::new(input);
         Ok(Self {
+            tags: parser.tags(),
             prefix: parser.prefix(),
             command: parser.command()?,
             args: parser.args(),
@@ -28,14 +30,165 @@ impl RawMessage {
         })
     }
 
-    pub fn into_message(self) -> Message {
-        Message {
-            sender: match self.prefix.unwrap() {
-                Prefix::User { nick, .. } => nick,
-                _ => panic!("invalid message kind"),
-            },
-            channel: { self.args }.remove(0),
-            data: self.data.unwrap(),
+    pub fn into_message(self) -> anyhow::Result<Message> {
+        let Self {
+            tags,
+            prefix,
+            args,
+            data,
+            ..
+        } = self;
+
+        let (sender, hostmask) = match prefix {
+            Some(Prefix::User { nick, user, host }) => {
+                let hostmask = format!("{}!{}@{}", nick, user, host);
+                (nick, hostmask)
+            }
+            prefix => anyhow::bail!("expected a user prefix, got: {:?}", prefix),
+        };
+        let channel = args
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("message did not have a target"))?;
+        let data = data.ok_or_else(|| anyhow::anyhow!("message did not have any data"))?;
+
+        Ok(Message {
+            sender,
+            hostmask: Some(hostmask),
+            channel,
+            data,
+            tags,
+        })
+    }
+
+    /// The parameters, with the trailing data last
+    pub fn params(&self) -> impl Iterator<Item = &str> + '_ {
+        self.args
+            .iter()
+            .chain(self.data.as_ref())
+            .map(|s| s.as_str())
+    }
+
+    pub fn param(&self, n: usize) -> Option<&str> {
+        self.params().nth(n)
+    }
+
+    /// The nickname or server name that sent this message
+    pub fn source(&self) -> Option<&str> {
+        match self.prefix.as_ref()? {
+            Prefix::User { nick, .. } => Some(nick),
+            Prefix::Server { host } => Some(host),
         }
     }
+
+    pub fn expect_nick(&self) -> anyhow::Result<&str> {
+        match &self.prefix {
+            Some(Prefix::User { nick, .. }) => Ok(nick),
+            prefix => anyhow::bail!(
+                "{:?} expected a user prefix, got: {:?}",
+                self.command,
+                prefix
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tagged() {
+        let msg = RawMessage::parse(
+            "@time=2020-06-01T12:30:45.000Z;account=museun :museun!~m@localhost PRIVMSG #test :hello world\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(msg.tags.account(), Some("museun"));
+        assert!(msg.tags.server_time().is_some());
+        assert!(matches!(msg.command, Command::Privmsg));
+        assert_eq!(msg.args, vec!["#test"]);
+        assert_eq!(msg.data.as_deref(), Some("hello world"));
+
+        let msg = msg.into_message().unwrap();
+        assert_eq!(msg.sender, "museun");
+        assert_eq!(msg.hostmask.as_deref(), Some("museun!~m@localhost"));
+        assert_eq!(msg.channel, "#test");
+        assert_eq!(msg.tags.account(), Some("museun"));
+    }
+
+    #[test]
+    fn parse_untagged() {
+        let msg = RawMessage::parse(":museun!~m@localhost PRIVMSG #test :hello\r\n").unwrap();
+        assert!(msg.tags.is_empty());
+        assert!(matches!(msg.prefix, Some(Prefix::User { .. })));
+        assert_eq!(msg.data.as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn parse_cap() {
+        let msg = RawMessage::parse(
+            ":irc.example.com CAP * LS :multi-prefix server-time account-tag\r\n",
+        )
+        .unwrap();
+        assert!(matches!(msg.command, Command::Cap));
+        assert_eq!(msg.args, vec!["*", "LS"]);
+        assert_eq!(
+            msg.data.as_deref(),
+            Some("multi-prefix server-time account-tag")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for input in &["", "hello", "\r\n", "@a=b\r\n", "@a=b :irc.example.com\r\n"] {
+            assert!(
+                RawMessage::parse(input).is_err(),
+                "{}",
+                input.escape_debug()
+            );
+        }
+    }
+
+    #[test]
+    fn into_message_query() {
+        let msg = RawMessage::parse(":museun!~m@localhost PRIVMSG #test :hello\r\n")
+            .unwrap()
+            .into_message()
+            .unwrap();
+        assert!(!msg.is_query());
+        assert_eq!(msg.reply_target(), "#test");
+
+        let msg = RawMessage::parse(":museun!~m@localhost PRIVMSG noye :hello\r\n")
+            .unwrap()
+            .into_message()
+            .unwrap();
+        assert!(msg.is_query());
+        assert_eq!(msg.reply_target(), "museun");
+    }
+
+    #[test]
+    fn into_message_errors() {
+        for input in &[
+            ":irc.example.com PRIVMSG #test :hello\r\n",
+            ":museun!~m@localhost PRIVMSG :hello\r\n",
+            ":museun!~m@localhost PRIVMSG #test\r\n",
+            "PRIVMSG #test :hello\r\n",
+        ] {
+            let msg = RawMessage::parse(input).unwrap();
+            assert!(msg.into_message().is_err(), "{}", input.escape_debug());
+        }
+    }
+
+    #[test]
+    fn parse_colon_in_args() {
+        let msg =
+            RawMessage::parse(":irc.example.com 333 noye #test nick!u@2001:db8::1 1591014645\r\n")
+                .unwrap();
+        assert_eq!(
+            msg.args,
+            vec!["noye", "#test", "nick!u@2001:db8::1", "1591014645"]
+        );
+        assert_eq!(msg.data, None);
+    }
 }
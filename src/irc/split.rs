@@ -0,0 +1,82 @@
+/// The most bytes a line can be, including the `\r\n`
+pub const MAX_LINE: usize = 512;
+
+/// Splits `text` into pieces of at most `max` bytes
+///
+/// This prefers splitting on spaces, and never splits inside of a UTF-8 character
+pub fn split(text: &str, max: usize) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut rest = text;
+
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        // a single character could be wider than the limit
+        if end == 0 {
+            end = rest.chars().next().map(char::len_utf8).unwrap_or(1);
+        }
+
+        // the space could be just past the limit
+        let space = match rest.as_bytes().get(end) {
+            Some(b' ') => Some(end),
+            _ => rest[..end].rfind(' '),
+        };
+
+        match space.filter(|&pos| pos > 0) {
+            Some(pos) => {
+                lines.push(&rest[..pos]);
+                rest = &rest[pos + 1..];
+            }
+            None => {
+                lines.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    if !rest.is_empty() || lines.is_empty() {
+        lines.push(rest);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short() {
+        assert_eq!(split("hello world", 20), vec!["hello world"]);
+        assert_eq!(split("", 20), vec![""]);
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(
+            split("the quick brown fox jumps over the lazy dog", 10),
+            vec!["the quick", "brown fox", "jumps over", "the lazy", "dog"]
+        );
+    }
+
+    #[test]
+    fn long_word() {
+        assert_eq!(
+            split("aaaaaaaaaaaaaaa bb", 10),
+            vec!["aaaaaaaaaa", "aaaaa bb"]
+        );
+    }
+
+    #[test]
+    fn utf8() {
+        // each of these is 3 bytes
+        let text = "ああああ";
+        assert_eq!(split(text, 7), vec!["ああ", "ああ"]);
+        assert_eq!(split(text, 2), vec!["あ", "あ", "あ", "あ"]);
+
+        for line in split("héllo wörld ünïcödé", 5) {
+            assert!(line.len() <= 5);
+        }
+    }
+}
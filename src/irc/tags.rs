@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+/// IRCv3 message tags
+///
+/// Values are stored unescaped. Tags sent without a value are stored with an empty value
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tags(HashMap<String, String>);
+
+impl Tags {
+    pub(crate) fn parse(input: &str) -> Self {
+        let map = input
+            .trim_start_matches('@')
+            .split(';')
+            .filter(|s| !s.is_empty())
+            .map(|tag| {
+                let mut iter = tag.splitn(2, '=');
+                let key = iter.next().unwrap().to_string();
+                let val = iter.next().map(unescape).unwrap_or_default();
+                (key, val)
+            })
+            .collect();
+        Self(map)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(|s| s.as_str())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// The `time` tag from the `server-time` capability
+    pub fn server_time(&self) -> Option<time::OffsetDateTime> {
+        // the format is `YYYY-MM-DDThh:mm:ss.sssZ`, but the fractional part is optional
+        let input = self.get("time")?;
+        let (head, nanos) = match input.find('.') {
+            Some(pos) => {
+                let fraction = input[pos + 1..].trim_end_matches('Z');
+                if fraction.is_empty() || !fraction.bytes().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                // this is a fraction of a second, so `.5` is 500ms
+                let nanos = format!("{:0<9.9}", fraction).parse::<i64>().ok()?;
+                (format!("{}Z", &input[..pos]), nanos)
+            }
+            None => (input.to_string(), 0),
+        };
+        time::OffsetDateTime::parse(head, time::Format::Rfc3339)
+            .ok()
+            .map(|dt| dt + time::Duration::nanoseconds(nanos))
+    }
+
+    /// The `account` tag from the `account-tag` capability
+    pub fn account(&self) -> Option<&str> {
+        self.get("account").filter(|s| !s.is_empty() && *s != "*")
+    }
+
+    /// The `msgid` tag from the `message-tags` capability
+    pub fn msgid(&self) -> Option<&str> {
+        self.get("msgid")
+    }
+}
+
+fn unescape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut iter = input.chars();
+    while let Some(ch) = iter.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        // a trailing backslash is dropped
+        match iter.next() {
+            Some(':') => out.push(';'),
+            Some('s') => out.push(' '),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some(ch) => out.push(ch),
+            None => break,
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let tags = Tags::parse("@aaa=bbb;ccc;example.com/ddd=eee");
+        assert_eq!(tags.get("aaa"), Some("bbb"));
+        assert_eq!(tags.get("ccc"), Some(""));
+        assert_eq!(tags.get("example.com/ddd"), Some("eee"));
+        assert!(tags.contains("ccc"));
+        assert!(!tags.contains("fff"));
+    }
+
+    #[test]
+    fn unescape_values() {
+        let tags =
+            Tags::parse(r"@a=hello\sworld;b=semi\:colon;c=back\\slash;d=crlf\r\n;e=trailing\;f=\q");
+        assert_eq!(tags.get("a"), Some("hello world"));
+        assert_eq!(tags.get("b"), Some("semi;colon"));
+        assert_eq!(tags.get("c"), Some(r"back\slash"));
+        assert_eq!(tags.get("d"), Some("crlf\r\n"));
+        assert_eq!(tags.get("e"), Some("trailing"));
+        assert_eq!(tags.get("f"), Some("q"));
+    }
+
+    #[test]
+    fn typed() {
+        let tags = Tags::parse("@time=2020-06-01T12:30:45.000Z;account=museun;msgid=abc");
+        let time = tags.server_time().unwrap();
+        assert_eq!(time.year(), 2020);
+        assert_eq!(time.hour(), 12);
+        assert_eq!(tags.account(), Some("museun"));
+        assert_eq!(tags.msgid(), Some("abc"));
+
+        let tags = Tags::parse("@account=*");
+        assert_eq!(tags.account(), None);
+    }
+
+    #[test]
+    fn server_time_fraction() {
+        let time = |input: &str| Tags::parse(&format!("@time={}", input)).server_time();
+        let nanos = |input| time(input).unwrap().nanosecond();
+
+        assert_eq!(nanos("2020-06-01T12:30:45Z"), 0);
+        assert_eq!(nanos("2020-06-01T12:30:45.5Z"), 500_000_000);
+        assert_eq!(nanos("2020-06-01T12:30:45.123Z"), 123_000_000);
+        assert_eq!(nanos("2020-06-01T12:30:45.123456Z"), 123_456_000);
+        assert_eq!(nanos("2020-06-01T12:30:45.1234567891Z"), 123_456_789);
+        assert_eq!(time("2020-06-01T12:30:45.123456Z").unwrap().second(), 45);
+
+        assert!(time("2020-06-01T12:30:45.Z").is_none());
+        assert!(time("2020-06-01T12:30:45.-1Z").is_none());
+    }
+}
@@ -7,11 +7,18 @@ pub struct LogFile(pub std::path::PathBuf);
 pub mod db;
 
 mod bot;
-pub use bot::{resolver, Context, Handler, Message, Responder, Runner, Writer, WriterResponder};
+pub use bot::{
+    args, config_watcher, resolver, scheduler, Args, AuthStatus, Authenticator, Capabilities,
+    Context, Cooldown, Cooldowns, Event, EventKind, EventsMap, Handler, JobContext, Latency,
+    Message, Registry, Responder, Role, Runner, Scheduler, Shutdown, TemplateStore, Tracker,
+    Writer, WriterResponder,
+};
 
 pub(crate) mod responses;
 
 pub mod config;
+pub mod connection;
+pub mod daemon;
 pub use config::{CachedConfig, Config};
 
 pub mod http;
@@ -1,29 +1,83 @@
 use super::*;
+use crate::daemon::Request;
 
 pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Result<()>
 where
     R: Responder + Send + 'static,
 {
-    init.commands.add("join", join)?;
-    init.commands.add("part", part)?;
-    init.commands.add("uptime", uptime)?;
-    init.commands.add("restart", restart)?;
-    init.commands.add("respawn", respawn)?;
-    init.commands.add("logs", get_logs)?;
+    init.commands
+        .add("join", join)?
+        .role(Role::Owner)
+        .query()
+        .args(join_args());
+    init.commands
+        .add("part", part)?
+        .role(Role::Owner)
+        .args(Args::new().description("leaves this channel"));
+    init.commands
+        .add("uptime", uptime)?
+        .query()
+        .args(Args::new().description("how long the bot has been running"));
+    init.commands
+        .add("lag", lag)?
+        .query()
+        .args(Args::new().description("the latency to the server"));
+    init.commands
+        .add("restart", restart)?
+        .role(Role::Owner)
+        .query()
+        .args(Args::new().description("restarts the bot"));
+    init.commands
+        .add("respawn", respawn)?
+        .role(Role::Owner)
+        .query()
+        .args(respawn_args());
+    init.commands
+        .add("quit", quit)?
+        .role(Role::Owner)
+        .query()
+        .args(quit_args());
+    init.commands
+        .add("logs", get_logs)?
+        .query()
+        .args(Args::new().description("uploads the log file"));
+    init.commands
+        .add("module", module)?
+        .role(Role::Owner)
+        .query()
+        .args(module_args());
+    init.commands
+        .add("templates", templates)?
+        .role(Role::Owner)
+        .query()
+        .args(
+            Args::new()
+                .description("reloads the templates file")
+                .subcommand("reload", Args::new())
+                .require_subcommand(),
+        );
+    init.commands
+        .add("jobs", jobs)?
+        .role(Role::Owner)
+        .query()
+        .args(Args::new().description("lists the scheduled jobs"));
+    init.commands.add("help", help)?.query().args(help_args());
 
     init.state.expect_insert(StartTime::default())
 }
 
-pub async fn join<R: Responder>(context: Context, mut responder: R) -> Result {
-    context.expect_owner(&mut responder).await?;
-    if let Some(chan) = context.command_args().get(0) {
-        return context.writer.clone().join(chan).await;
-    }
-    responder.reply(context, Join::ExpectedChannel).await
+fn join_args() -> Args {
+    Args::new()
+        .description("joins a channel")
+        .arg("channel", Kind::Channel)
+}
+
+pub async fn join<R: Responder>(context: Context, _: R) -> Result {
+    let chan = context.arguments.expect_str("channel")?;
+    context.writer.clone().join(chan).await
 }
 
-pub async fn part<R: Responder>(mut context: Context, mut responder: R) -> Result {
-    context.expect_owner(&mut responder).await?;
+pub async fn part<R: Responder>(mut context: Context, _: R) -> Result {
     let chan = &context.args.channel;
     context.writer.part(chan).await
 }
@@ -38,6 +92,151 @@ pub async fn uptime<R: Responder>(context: Context, mut responder: R) -> Result
     responder.say(context, Uptime::Uptime { uptime }).await
 }
 
+pub async fn lag<R: Responder>(context: Context, mut responder: R) -> Result {
+    let last = context.state.lock().await.expect_get::<Latency>()?.last();
+    let resp = match last {
+        Some(lag) => Lag::Lag {
+            lag: format!("{}ms", lag.as_millis()),
+        },
+        None => Lag::Unknown,
+    };
+    responder.say(context, resp).await
+}
+
+fn module_toggle_args() -> Args {
+    Args::new()
+        .arg("module", Kind::Word)
+        .optional("channel", Kind::Channel)
+}
+
+fn module_args() -> Args {
+    Args::new()
+        .description("turns a module on or off in a channel")
+        .subcommand("enable", module_toggle_args())
+        .subcommand("disable", module_toggle_args())
+        .require_subcommand()
+}
+
+pub async fn module<R: Responder>(context: Context, mut responder: R) -> Result {
+    let args = &context.arguments;
+    let subcommand = args.subcommand().unwrap_or("enable");
+    let enabled = subcommand == "enable";
+    let module = args.expect_str("module")?.to_string();
+    // there's no channel to default to in a query
+    let channel = match args.str("channel") {
+        Some(channel) => channel.to_string(),
+        None if context.is_query() => {
+            let usage = module_toggle_args().usage(&format!("module {}", subcommand));
+            let resp = Usage::Missing {
+                arg: "channel".into(),
+                usage,
+            };
+            return responder.reply(context.clone(), resp).await;
+        }
+        None => context.room().to_string(),
+    };
+
+    let resp = {
+        let state = context.state.lock().await;
+        let filter = state.expect_get::<ModuleFilter>()?;
+        if !filter.is_known(&module) {
+            let known = filter.known().collect::<Vec<_>>().join(", ");
+            Module::Unknown { module, known }
+        } else {
+            filter.set(&context.key_for(&channel), &module, enabled)?;
+            if enabled {
+                Module::Enabled { module, channel }
+            } else {
+                Module::Disabled { module, channel }
+            }
+        }
+    };
+    responder.reply(context.clone(), resp).await
+}
+
+pub async fn jobs<R: Responder>(context: Context, mut responder: R) -> Result {
+    let scheduler = context
+        .state
+        .lock()
+        .await
+        .expect_get::<Scheduler>()?
+        .clone();
+
+    let now = time::OffsetDateTime::now_utc();
+    let jobs = scheduler
+        .jobs(context.network())?
+        .into_iter()
+        .map(|job| match job.next {
+            Some(next) if next > now => format!(
+                "{} ({}, next in {})",
+                job.name,
+                job.schedule,
+                (next - now).as_readable_time()
+            ),
+            Some(..) => format!("{} ({}, next now)", job.name, job.schedule),
+            None => format!("{} ({})", job.name, job.schedule),
+        })
+        .collect::<Vec<_>>();
+
+    let resp = if jobs.is_empty() {
+        Jobs::Empty
+    } else {
+        Jobs::Listing {
+            jobs: jobs.join(", "),
+        }
+    };
+    responder.say(context, resp).await
+}
+
+pub async fn templates<R: Responder>(context: Context, mut responder: R) -> Result {
+    let resolver = context.state.lock().await.expect_get::<Resolver>()?.clone();
+    let resp = match resolver.lock().await.reload() {
+        Ok(..) => Templates::Reloaded,
+        Err(err) => Templates::Invalid {
+            error: format!("{:#}", err),
+        },
+    };
+    responder.reply(context, resp).await
+}
+
+fn help_args() -> Args {
+    Args::new()
+        .description("lists the commands, or shows how to use one")
+        .optional("command", Kind::Word)
+}
+
+pub async fn help<R: Responder>(context: Context, mut responder: R) -> Result {
+    let role = context.role().await?;
+    let resp = {
+        let state = context.state.lock().await;
+        let index = state.expect_get::<CommandIndex>()?;
+        match context.arguments.str("command") {
+            Some(command) => {
+                let command = command.trim_start_matches('!');
+                match index.get(command).filter(|(required, _)| *required <= role) {
+                    Some((_, args)) => {
+                        let usage = args.usages(command).join(" | ");
+                        match args.get_description() {
+                            Some(description) => Help::Command {
+                                usage,
+                                description: description.to_string(),
+                            },
+                            None => Help::Usage { usage },
+                        }
+                    }
+                    None => Help::Unknown {
+                        command: command.to_string(),
+                    },
+                }
+            }
+            None => Help::Commands {
+                commands: index.available(role).collect::<Vec<_>>().join(", "),
+            },
+        }
+    };
+    responder.reply(context.clone(), resp).await
+}
+
 pub async fn get_logs<R: Responder>(context: Context, mut responder: R) -> Result {
     let state = context.state.lock().await;
     let temp = state.expect_get::<crate::http::server::TempStore>()?;
@@ -59,23 +258,47 @@ pub async fn get_logs<R: Responder>(context: Context, mut responder: R) -> Resul
     responder.say(context.clone(), template).await
 }
 
-pub async fn restart<R: Responder>(context: Context, mut responder: R) -> Result {
-    context.expect_owner(&mut responder).await?;
+pub async fn restart<R: Responder>(context: Context, _: R) -> Result {
     let addr = context.config().await?.modules.restart.address;
-    Supervisor::Restart.write(&addr).await
+    Request::Restart.send(&addr).await?;
+    context.quit.stop(Some("restarting".into()));
+    Ok(())
+}
+
+fn respawn_args() -> Args {
+    Args::new()
+        .description("restarts the bot after a delay, in seconds")
+        .optional("delay", Kind::Number)
 }
 
-pub async fn respawn<R: Responder>(context: Context, mut responder: R) -> Result {
-    context.expect_owner(&mut responder).await?;
+pub async fn respawn<R: Responder>(context: Context, _: R) -> Result {
     let delay = context
-        .command_args()
-        .get(0)
-        .and_then(|s| s.parse().ok())
+        .arguments
+        .number("delay")
+        .and_then(|d| std::convert::TryFrom::try_from(d).ok())
         .unwrap_or(15);
     let addr = context.config().await?.modules.restart.address;
-    Supervisor::Delay(delay).write(&addr).await
+    Request::Delay(delay).send(&addr).await?;
+    context
+        .quit
+        .stop(Some(format!("restarting in {} seconds", delay)));
+    Ok(())
 }
 
+fn quit_args() -> Args {
+    Args::new()
+        .description("disconnects from every network and stops the bot")
+        .optional("reason", Kind::Text)
+}
+
+pub async fn quit<R: Responder>(context: Context, _: R) -> Result {
+    let reason = context.arguments.str("reason").map(ToString::to_string);
+    log::info!("{} asked us to quit", context.args.sender);
+    context.quit.stop(reason);
+    Ok(())
+}
+
+#[derive(Clone)]
 pub struct StartTime(pub tokio::time::Instant);
 
 impl Default for StartTime {
@@ -90,33 +313,6 @@ impl StartTime {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-enum Supervisor {
-    Restart,
-    Delay(u16),
-}
-
-impl Supervisor {
-    async fn write(self, addr: &str) -> anyhow::Result<()> {
-        use tokio::{io::*, net::TcpStream};
-        let mut stream = TcpStream::connect(addr)
-            .await
-            .with_context(|| format!("cannot connect to restart daemon at '{}'", addr))?;
-
-        match self {
-            Self::Restart => stream.write_all(b"RESTART\0").await?,
-            Self::Delay(d) => {
-                stream
-                    .write_all(format!("DELAY {}\0", d).as_bytes())
-                    .await?
-            }
-        }
-
-        stream.flush().await?;
-        Ok(())
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use crate::test::*;
@@ -126,7 +322,10 @@ mod tests {
     async fn join_not_owner() {
         set_snapshot_path();
 
-        let responses = TestEnv::new("!join").execute(super::join).await;
+        let responses = TestEnv::new("!join")
+            .requires(Role::Owner)
+            .execute(super::join)
+            .await;
         insta::assert_yaml_snapshot!(responses.get_reply::<responses::Builtin>());
         responses.expect_empty();
     }
@@ -135,8 +334,12 @@ mod tests {
     async fn join_no_channel() {
         set_snapshot_path();
 
-        let responses = TestEnv::new("!join").owner().execute(super::join).await;
-        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Join>());
+        let responses = TestEnv::new("!join")
+            .owner()
+            .args(super::join_args())
+            .execute(super::join)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Usage>());
         responses.expect_empty();
     }
 
@@ -146,17 +349,93 @@ mod tests {
 
         let responses = TestEnv::new("!join #test")
             .owner()
+            .args(super::join_args())
+            .execute(super::join)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_raw());
+        responses.expect_empty();
+    }
+
+    #[tokio::test]
+    async fn join_query() {
+        set_snapshot_path();
+
+        // queries don't need a prefix
+        let responses = TestEnv::new("join #test")
+            .query()
+            .owner()
+            .args(super::join_args())
             .execute(super::join)
             .await;
         insta::assert_yaml_snapshot!(responses.get_raw());
         responses.expect_empty();
     }
 
+    #[tokio::test]
+    async fn templates() {
+        set_snapshot_path();
+
+        let path = std::env::temp_dir().join(format!("noye-reload-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = TemplateStore::new(crate::DEFAULT_TEMPLATES, &path).unwrap();
+        let resolver = crate::resolver::new(store);
+
+        let responses = TestEnv::new("!templates reload")
+            .insert(resolver.clone())
+            .execute(super::templates)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Templates>());
+        responses.expect_empty();
+
+        std::fs::write(&path, "[lag]\nlag = 'lag ${lag}'\n").unwrap();
+        let responses = TestEnv::new("!templates reload")
+            .insert(resolver.clone())
+            .execute(super::templates)
+            .await;
+        responses.get_reply::<responses::Templates>();
+        responses.expect_empty();
+        assert_eq!(
+            resolver.lock().await.get(None, "lag", "lag"),
+            Some("lag ${lag}")
+        );
+
+        std::fs::write(&path, "[lag]\nlag = 'lag ${lagg}'\n").unwrap();
+        let responses = TestEnv::new("!templates reload")
+            .insert(resolver.clone())
+            .execute(super::templates)
+            .await;
+        assert!(matches!(
+            responses.get_reply::<responses::Templates>(),
+            responses::Templates::Invalid { .. }
+        ));
+        responses.expect_empty();
+        assert_eq!(
+            resolver.lock().await.get(None, "lag", "lag"),
+            Some("lag ${lag}")
+        );
+
+        std::fs::write(&path, "[lag").unwrap();
+        let responses = TestEnv::new("!templates reload")
+            .insert(resolver)
+            .execute(super::templates)
+            .await;
+        assert!(matches!(
+            responses.get_reply::<responses::Templates>(),
+            responses::Templates::Invalid { .. }
+        ));
+        responses.expect_empty();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
     #[tokio::test]
     async fn part_not_owner() {
         set_snapshot_path();
 
-        let responses = TestEnv::new("!part").execute(super::part).await;
+        let responses = TestEnv::new("!part")
+            .requires(Role::Owner)
+            .execute(super::part)
+            .await;
         insta::assert_yaml_snapshot!(responses.get_reply::<responses::Builtin>());
         responses.expect_empty();
     }
@@ -186,6 +465,153 @@ mod tests {
         responses.expect_empty();
     }
 
+    #[tokio::test]
+    async fn lag() {
+        set_snapshot_path();
+
+        tokio::time::pause();
+        let latency = crate::Latency::default();
+
+        let responses = TestEnv::new("!lag")
+            .insert(latency.clone())
+            .execute(super::lag)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_say::<responses::Lag>());
+        responses.expect_empty();
+
+        latency.ping("noye-1");
+        tokio::time::advance(std::time::Duration::from_millis(150)).await;
+        latency.pong("noye-1");
+
+        let responses = TestEnv::new("!lag")
+            .insert(latency)
+            .execute(super::lag)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_say::<responses::Lag>());
+        responses.expect_empty();
+    }
+
+    #[tokio::test]
+    async fn module() {
+        set_snapshot_path();
+
+        let filter = || ModuleFilter::new(vec!["pictures", "repost"]);
+
+        let responses = TestEnv::new("!module enable")
+            .insert(filter())
+            .args(super::module_args())
+            .execute(super::module)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Usage>());
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!module disable youtube")
+            .insert(filter())
+            .args(super::module_args())
+            .execute(super::module)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Module>());
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!module disable pictures")
+            .insert(filter())
+            .args(super::module_args())
+            .execute(super::module)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Module>());
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!module enable repost #other")
+            .insert(filter())
+            .args(super::module_args())
+            .execute(super::module)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Module>());
+        responses.expect_empty();
+
+        // a query needs the channel
+        let responses = TestEnv::new("!module disable pictures")
+            .query()
+            .insert(filter())
+            .args(super::module_args())
+            .execute(super::module)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Usage>());
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!module disable pictures #test")
+            .query()
+            .insert(filter())
+            .args(super::module_args())
+            .execute(super::module)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Module>());
+        responses.expect_empty();
+    }
+
+    #[tokio::test]
+    async fn help() {
+        set_snapshot_path();
+
+        let index = || {
+            let mut commands = CommandsMap::<YamlResponder>::default();
+            commands
+                .add("join", super::join)
+                .unwrap()
+                .role(Role::Owner)
+                .args(super::join_args());
+            commands.add("lag", super::lag).unwrap();
+            commands
+                .add("help", super::help)
+                .unwrap()
+                .args(super::help_args());
+            commands.index()
+        };
+
+        let responses = TestEnv::new("!help")
+            .insert(index())
+            .args(super::help_args())
+            .execute(super::help)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Help>());
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!help")
+            .owner()
+            .insert(index())
+            .args(super::help_args())
+            .execute(super::help)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Help>());
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!help !join")
+            .owner()
+            .insert(index())
+            .args(super::help_args())
+            .execute(super::help)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Help>());
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!help lag")
+            .insert(index())
+            .args(super::help_args())
+            .execute(super::help)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Help>());
+        responses.expect_empty();
+
+        // commands the user can't use are hidden
+        let responses = TestEnv::new("!help join")
+            .insert(index())
+            .args(super::help_args())
+            .execute(super::help)
+            .await;
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Help>());
+        responses.expect_empty();
+    }
+
     #[tokio::test]
     async fn restart_not_owner() {
         set_snapshot_path();
@@ -195,6 +621,7 @@ mod tests {
 
         let responses = TestEnv::new("!restart")
             .config(|config| config.modules.restart.address = addr.clone())
+            .requires(Role::Owner)
             .execute(super::restart)
             .await;
         insta::assert_yaml_snapshot!(responses.get_reply::<responses::Builtin>());
@@ -206,17 +633,21 @@ mod tests {
     async fn restart() {
         set_snapshot_path();
 
-        let mut listen = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
+        let listen = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
         let addr = listen.local_addr().unwrap().to_string();
+        let daemon = daemon(listen);
 
+        let shutdown = Shutdown::default();
         let responses = TestEnv::new("!restart")
             .config(|config| config.modules.restart.address = addr)
+            .insert(shutdown.clone())
             .owner()
             .execute(super::restart)
             .await;
         responses.expect_empty();
-        let data = accept_and_read(&mut listen).await;
+        let data = daemon.await.unwrap();
         assert_eq!(data, b"RESTART\0");
+        assert_eq!(shutdown.stopping().unwrap().reason.unwrap(), "restarting");
     }
 
     #[tokio::test]
@@ -228,6 +659,7 @@ mod tests {
 
         let responses = TestEnv::new("!respawn")
             .config(|config| config.modules.restart.address = addr.clone())
+            .requires(Role::Owner)
             .execute(super::respawn)
             .await;
         insta::assert_yaml_snapshot!(responses.get_reply::<responses::Builtin>());
@@ -239,8 +671,9 @@ mod tests {
     async fn respawn_default() {
         set_snapshot_path();
 
-        let mut listen = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
+        let listen = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
         let addr = listen.local_addr().unwrap().to_string();
+        let daemon = daemon(listen);
 
         let responses = TestEnv::new("!respawn")
             .config(|config| config.modules.restart.address = addr.clone())
@@ -248,7 +681,7 @@ mod tests {
             .execute(super::respawn)
             .await;
         responses.expect_empty();
-        let data = accept_and_read(&mut listen).await;
+        let data = daemon.await.unwrap();
         assert_eq!(data, b"DELAY 15\0");
     }
 
@@ -256,23 +689,120 @@ mod tests {
     async fn respawn_custom() {
         set_snapshot_path();
 
-        let mut listen = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
+        let listen = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
         let addr = listen.local_addr().unwrap().to_string();
+        let daemon = daemon(listen);
 
+        let shutdown = Shutdown::default();
         let responses = TestEnv::new("!respawn 30")
             .config(|config| config.modules.restart.address = addr)
+            .insert(shutdown.clone())
             .owner()
+            .args(super::respawn_args())
             .execute(super::respawn)
             .await;
         responses.expect_empty();
-        let data = accept_and_read(&mut listen).await;
+        let data = daemon.await.unwrap();
         assert_eq!(data, b"DELAY 30\0");
+        assert_eq!(
+            shutdown.stopping().unwrap().reason.unwrap(),
+            "restarting in 30 seconds"
+        );
+    }
+
+    #[tokio::test]
+    async fn quit() {
+        let shutdown = Shutdown::default();
+        let responses = TestEnv::new("!quit")
+            .insert(shutdown.clone())
+            .requires(Role::Owner)
+            .args(super::quit_args())
+            .execute(super::quit)
+            .await;
+        let _ = responses.get_reply::<responses::Builtin>();
+        responses.expect_empty();
+        assert!(shutdown.stopping().is_none());
+
+        let responses = TestEnv::new("!quit see you later")
+            .insert(shutdown.clone())
+            .owner()
+            .args(super::quit_args())
+            .execute(super::quit)
+            .await;
+        responses.expect_empty();
+        assert_eq!(
+            shutdown.stopping(),
+            Some(Stop {
+                reason: Some("see you later".into())
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn jobs() {
+        let _db = crate::db::get_connection();
+
+        let scheduler = Scheduler::default();
+        let responses = TestEnv::new("!jobs")
+            .insert(scheduler.clone())
+            .requires(Role::Owner)
+            .execute(super::jobs)
+            .await;
+        let _ = responses.get_reply::<responses::Builtin>();
+        responses.expect_empty();
+
+        let responses = TestEnv::new("!jobs")
+            .insert(scheduler.clone())
+            .owner()
+            .execute(super::jobs)
+            .await;
+        assert!(matches!(
+            responses.get_say::<responses::Jobs>(),
+            responses::Jobs::Empty
+        ));
+        responses.expect_empty();
+
+        let every = tokio::time::Duration::from_secs(5 * 60);
+        scheduler
+            .every("tick", every, |_| async { Ok(()) })
+            .unwrap();
+        scheduler
+            .cron("daily", "0 9 * * *", |_| async { Ok(()) })
+            .unwrap();
+        scheduler.on_due("remind", |_, _| async { Ok(()) }).unwrap();
+        let due = time::OffsetDateTime::now_utc() + time::Duration::hours(2);
+        scheduler.once(None, "remind", due, "").unwrap();
+        scheduler.once(Some("other"), "remind", due, "").unwrap();
+
+        let responses = TestEnv::new("!jobs")
+            .insert(scheduler)
+            .owner()
+            .execute(super::jobs)
+            .await;
+        match responses.get_say::<responses::Jobs>() {
+            // the one-shot job on the other network isn't listed
+            responses::Jobs::Listing { jobs } => assert!(
+                jobs.starts_with(
+                    "tick (every 5 minutes), daily (at '0 9 * * *'), \
+                     remind (once, next in 1 hour, 59 minutes"
+                ),
+                "{}",
+                jobs
+            ),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        responses.expect_empty();
     }
 
-    async fn accept_and_read(listener: &mut tokio::net::TcpListener) -> Vec<u8> {
-        let (mut socket, _) = listener.accept().await.unwrap();
-        let mut data = vec![];
-        let _ = socket.read_to_end(&mut data).await.unwrap();
-        data
+    /// Acts like the restart daemon for one request, returning what was sent
+    fn daemon(mut listener: tokio::net::TcpListener) -> tokio::task::JoinHandle<Vec<u8>> {
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = tokio::io::BufReader::new(socket);
+            let mut data = vec![];
+            socket.read_until(b'\0', &mut data).await.unwrap();
+            socket.write_all(b"OK\0").await.unwrap();
+            data
+        })
     }
 }
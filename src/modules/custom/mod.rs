@@ -0,0 +1,132 @@
+use super::*;
+
+table!(CustomTable => "./sql/schema.sql");
+
+pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Result<()>
+where
+    R: Responder + Send + 'static,
+{
+    let aliases = persist::aliases()?.into_iter().collect::<Aliases>();
+    init.state.expect_insert(aliases)?;
+
+    init.commands
+        .add("cmd", manage)?
+        .role(Role::Owner)
+        .query()
+        .args(args());
+    init.passives.add("custom", hear_custom);
+    Ok(())
+}
+
+fn args() -> Args {
+    let name = || Args::new().arg("name", Kind::Word);
+    Args::new()
+        .description("manages the custom commands and aliases")
+        .subcommand("add", name().arg("text", Kind::Text))
+        .subcommand("alias", name().arg("command", Kind::Text))
+        .subcommand("remove", name())
+        .subcommand("list", Args::new())
+        .require_subcommand()
+}
+
+pub async fn manage<R: Responder>(context: Context, mut responder: R) -> Result {
+    let args = &context.arguments;
+    let name = args
+        .str("name")
+        .map(|name| name.trim_start_matches('!').to_string());
+
+    let resp = match (args.subcommand(), name) {
+        (Some("add"), Some(name)) => {
+            let text = args.expect_str("text")?;
+            if is_command(&context, &name).await? || !persist::add_command(&name, text)? {
+                responses::Custom::Exists { name }
+            } else {
+                responses::Custom::Added { name }
+            }
+        }
+
+        (Some("alias"), Some(name)) => {
+            let command = args.expect_str("command")?.trim_start_matches('!');
+            let head = command.split(' ').next().unwrap_or_default();
+            if !is_command(&context, head).await? {
+                responses::Custom::UnknownCommand {
+                    command: head.to_string(),
+                }
+            } else if is_command(&context, &name).await? || !persist::add_alias(&name, command)? {
+                responses::Custom::Exists { name }
+            } else {
+                let state = context.state.lock().await;
+                state.expect_get::<Aliases>()?.insert(&name, command);
+                responses::Custom::Aliased {
+                    name,
+                    command: command.to_string(),
+                }
+            }
+        }
+
+        (Some("remove"), Some(name)) => {
+            if persist::remove(&name)? {
+                let state = context.state.lock().await;
+                state.expect_get::<Aliases>()?.remove(&name);
+                responses::Custom::Removed { name }
+            } else {
+                responses::Custom::NotFound { name }
+            }
+        }
+
+        _ => {
+            let list = |items: Vec<String>| {
+                if items.is_empty() {
+                    return "none".to_string();
+                }
+                items.join(", ")
+            };
+            let aliases = persist::aliases()?
+                .into_iter()
+                .map(|(name, command)| format!("{} -> {}", name, command))
+                .collect();
+            responses::Custom::Listing {
+                commands: list(persist::commands()?),
+                aliases: list(aliases),
+            }
+        }
+    };
+
+    responder.reply(context.clone(), resp).await
+}
+
+/// Whether `name` is already a command, a custom command, or an alias for one
+async fn is_command(context: &Context, name: &str) -> anyhow::Result<bool> {
+    Ok(is_reserved(context, name).await? || persist::get_command(name)?.is_some())
+}
+
+/// Whether `name` is a module's command, or an alias
+async fn is_reserved(context: &Context, name: &str) -> anyhow::Result<bool> {
+    let state = context.state.lock().await;
+    let index = state.expect_get::<CommandIndex>()?;
+    let alias = state.get::<Aliases>().and_then(|aliases| aliases.get(name));
+    Ok(index.get(name).is_some() || alias.is_some())
+}
+
+pub async fn hear_custom<R: Responder>(context: Context, mut responder: R) -> Result {
+    let name = match context.command() {
+        Some(name) if !is_reserved(&context, name).await? => name,
+        _ => return crate::util::dont_care(),
+    };
+
+    let text = match persist::get_command(name)? {
+        Some(body) => body
+            .replace("${nick}", context.nick())
+            .replace("${channel}", context.room()),
+        None => return crate::util::dont_care(),
+    };
+
+    responder
+        .say(context.clone(), responses::Custom::Text { text })
+        .await
+}
+
+mod persist;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,71 @@
+use super::CustomTable;
+
+/// Gets the body of the custom command `name`
+pub fn get_command(name: &str) -> anyhow::Result<Option<String>> {
+    let conn = crate::db::get::<CustomTable>();
+    let mut stmt = conn.prepare("SELECT body FROM custom_commands WHERE name = :name")?;
+    let mut iter = stmt.query_map_named(
+        rusqlite::named_params! {
+            ":name": name,
+        },
+        |row| row.get("body"),
+    )?;
+    Ok(iter.next().transpose()?)
+}
+
+/// Adds a custom command, this returns false if one already exists with that name
+pub fn add_command(name: &str, body: &str) -> anyhow::Result<bool> {
+    let conn = crate::db::get::<CustomTable>();
+    let n = conn.execute_named(
+        "INSERT OR IGNORE INTO custom_commands (name, body) VALUES (:name, :body)",
+        rusqlite::named_params! {
+            ":name": name,
+            ":body": body,
+        },
+    )?;
+    Ok(n == 1)
+}
+
+/// Adds an alias, this returns false if one already exists with that name
+pub fn add_alias(name: &str, command: &str) -> anyhow::Result<bool> {
+    let conn = crate::db::get::<CustomTable>();
+    let n = conn.execute_named(
+        "INSERT OR IGNORE INTO aliases (name, command) VALUES (:name, :command)",
+        rusqlite::named_params! {
+            ":name": name,
+            ":command": command,
+        },
+    )?;
+    Ok(n == 1)
+}
+
+/// Removes the custom command or alias called `name`
+pub fn remove(name: &str) -> anyhow::Result<bool> {
+    let conn = crate::db::get::<CustomTable>();
+    let mut n = 0;
+    for query in &[
+        "DELETE FROM custom_commands WHERE name = :name",
+        "DELETE FROM aliases WHERE name = :name",
+    ] {
+        n += conn.execute_named(query, rusqlite::named_params! { ":name": name })?;
+    }
+    Ok(n > 0)
+}
+
+/// The names of all of the custom commands
+pub fn commands() -> anyhow::Result<Vec<String>> {
+    let conn = crate::db::get::<CustomTable>();
+    let mut stmt = conn.prepare("SELECT name FROM custom_commands ORDER BY name")?;
+    let iter = stmt.query_map(rusqlite::NO_PARAMS, |row| row.get("name"))?;
+    Ok(iter.collect::<Result<_, _>>()?)
+}
+
+/// All of the aliases, and the commands they are for
+pub fn aliases() -> anyhow::Result<Vec<(String, String)>> {
+    let conn = crate::db::get::<CustomTable>();
+    let mut stmt = conn.prepare("SELECT name, command FROM aliases ORDER BY name")?;
+    let iter = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+        Ok((row.get("name")?, row.get("command")?))
+    })?;
+    Ok(iter.collect::<Result<_, _>>()?)
+}
@@ -0,0 +1,13 @@
+-- commands that reply with some text
+CREATE TABLE IF NOT EXISTS custom_commands (
+    `name` TEXT NOT NULL,
+    `body` TEXT NOT NULL,
+    UNIQUE(name)
+);
+
+-- other names for commands, the command can include arguments
+CREATE TABLE IF NOT EXISTS aliases (
+    `name` TEXT NOT NULL,
+    `command` TEXT NOT NULL,
+    UNIQUE(name)
+)
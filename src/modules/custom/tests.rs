@@ -0,0 +1,137 @@
+use super::*;
+use crate::test::*;
+
+#[test]
+fn persist() {
+    let _db = crate::db::get::<CustomTable>();
+
+    assert!(persist::add_command("rules", "be nice").unwrap());
+    assert!(!persist::add_command("rules", "be mean").unwrap());
+    assert_eq!(
+        persist::get_command("rules").unwrap().as_deref(),
+        Some("be nice")
+    );
+    assert!(persist::get_command("other").unwrap().is_none());
+
+    assert!(persist::add_alias("p", "pictures").unwrap());
+    assert!(!persist::add_alias("p", "pictures refresh").unwrap());
+    assert_eq!(
+        persist::aliases().unwrap(),
+        vec![("p".to_string(), "pictures".to_string())]
+    );
+
+    assert!(persist::remove("rules").unwrap());
+    assert!(persist::remove("p").unwrap());
+    assert!(!persist::remove("p").unwrap());
+    assert!(persist::commands().unwrap().is_empty());
+    assert!(persist::aliases().unwrap().is_empty());
+}
+
+fn index() -> CommandIndex {
+    let mut commands = CommandsMap::<YamlResponder>::default();
+    commands.add("pictures", super::manage).unwrap();
+    commands.add("cmd", super::manage).unwrap();
+    commands.index()
+}
+
+fn cmd(data: &str) -> TestEnv {
+    TestEnv::new(data)
+        .owner()
+        .insert(index())
+        .insert(Aliases::default())
+        .args(super::args())
+}
+
+#[tokio::test]
+async fn custom_commands() {
+    set_snapshot_path();
+    let _db = crate::db::get_connection();
+
+    let responses = cmd("!cmd add !rules be nice, ${nick}. this is ${channel}")
+        .execute(super::manage)
+        .await;
+    insta::assert_yaml_snapshot!(responses.get_reply::<responses::Custom>());
+    responses.expect_empty();
+
+    let responses = cmd("!cmd add pictures something")
+        .execute(super::manage)
+        .await;
+    insta::assert_yaml_snapshot!(responses.get_reply::<responses::Custom>());
+    responses.expect_empty();
+
+    let responses = TestEnv::new("!rules")
+        .insert(index())
+        .execute(super::hear_custom)
+        .await;
+    insta::assert_yaml_snapshot!(responses.get_say::<responses::Custom>());
+    responses.expect_empty();
+
+    let responses = TestEnv::new("!pictures")
+        .insert(index())
+        .execute(super::hear_custom)
+        .await;
+    responses.expect_empty();
+
+    let responses = cmd("!cmd remove rules").execute(super::manage).await;
+    insta::assert_yaml_snapshot!(responses.get_reply::<responses::Custom>());
+    responses.expect_empty();
+
+    let responses = cmd("!cmd remove rules").execute(super::manage).await;
+    insta::assert_yaml_snapshot!(responses.get_reply::<responses::Custom>());
+    responses.expect_empty();
+}
+
+#[tokio::test]
+async fn aliases() {
+    set_snapshot_path();
+    let _db = crate::db::get_connection();
+
+    let responses = cmd("!cmd alias p pictures refresh")
+        .execute(super::manage)
+        .await;
+    insta::assert_yaml_snapshot!(responses.get_reply::<responses::Custom>());
+    responses.expect_empty();
+
+    let responses = cmd("!cmd alias yt youtube").execute(super::manage).await;
+    insta::assert_yaml_snapshot!(responses.get_reply::<responses::Custom>());
+    responses.expect_empty();
+
+    let responses = cmd("!cmd add foo bar").execute(super::manage).await;
+    responses.get_reply::<responses::Custom>();
+    responses.expect_empty();
+
+    let responses = cmd("!cmd list").execute(super::manage).await;
+    insta::assert_yaml_snapshot!(responses.get_reply::<responses::Custom>());
+    responses.expect_empty();
+}
+
+#[tokio::test]
+async fn names_are_shared() {
+    let _db = crate::db::get_connection();
+
+    let reply = |responses: YamlResponder| {
+        let resp = responses.get_reply::<responses::Custom>();
+        responses.expect_empty();
+        resp
+    };
+
+    let responses = cmd("!cmd add rules be nice").execute(super::manage).await;
+    assert!(matches!(reply(responses), responses::Custom::Added { .. }));
+
+    // an alias can't hide a custom command
+    let responses = cmd("!cmd alias rules pictures")
+        .execute(super::manage)
+        .await;
+    assert!(matches!(reply(responses), responses::Custom::Exists { .. }));
+
+    // but it can be for one
+    let responses = cmd("!cmd alias r rules").execute(super::manage).await;
+    assert!(matches!(
+        reply(responses),
+        responses::Custom::Aliased { .. }
+    ));
+    assert_eq!(
+        persist::aliases().unwrap(),
+        vec![("r".to_string(), "rules".to_string())]
+    );
+}
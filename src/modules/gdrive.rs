@@ -6,10 +6,10 @@ pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> Result
 where
     R: Responder + Send + 'static,
 {
-    init.passives.add(hear_gdrive);
+    init.passives.add("gdrive", hear_gdrive);
 
     let client = GDriveClient::new(
-        &init.state.config().await?.modules.gdrive.api_key,
+        &init.state.config()?.modules.gdrive.api_key,
         crate::http::client::new_client(),
     );
     init.state.expect_insert(client)
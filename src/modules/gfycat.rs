@@ -4,7 +4,7 @@ pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Re
 where
     R: Responder + Send + 'static,
 {
-    init.passives.add(gfycat);
+    init.passives.add("gfycat", gfycat);
 
     Ok(())
 }
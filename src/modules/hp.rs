@@ -4,18 +4,20 @@ pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> Result
 where
     R: Responder + Send + 'static,
 {
-    init.commands.add("hp", get_info)?;
+    init.commands
+        .add("hp", get_info)?
+        .args(
+            Args::new()
+                .description("shows the set list of a concert")
+                .arg("id", args::Kind::Text),
+        )
+        .cooldown(Cooldown::default().user(std::time::Duration::from_secs(30)))
+        .module("hp");
     Ok(())
 }
 
 async fn get_info<R: Responder>(context: Context, mut responder: R) -> Result {
-    let id = match context.without_command() {
-        Some(args) => args.trim().to_uppercase(),
-        None => {
-            let resp = responses::Concert::NoIdProvided;
-            return responder.reply(context.clone(), resp).await;
-        }
-    };
+    let id = context.arguments.expect_str("id")?.trim().to_uppercase();
 
     let concert = lookup(&id).await?;
     let mcs = concert.sum_mcs();
@@ -9,7 +9,7 @@ pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Re
 where
     R: Responder + Send + 'static,
 {
-    init.passives.add(hear_instagram);
+    init.passives.add("instagram", hear_instagram);
     Ok(())
 }
 
@@ -6,6 +6,7 @@ pub(self) use crate::{bot::*, db::Table, responses::*, util::*, *};
 pub(self) use futures::prelude::*;
 
 mod builtin;
+mod custom;
 mod gdrive;
 mod gfycat;
 mod hp;
@@ -20,6 +21,10 @@ mod youtube;
 pub struct ModuleInit<R> {
     pub commands: CommandsMap<R>,
     pub passives: PassivesList<R>,
+    /// Handlers for the other IRC events, e.g. someone joining a channel
+    pub events: EventsMap<R>,
+    /// Jobs that run on a timer, these are started once we're connecting
+    pub scheduler: Scheduler,
     pub state: State,
 }
 
@@ -28,6 +33,8 @@ impl<R> Default for ModuleInit<R> {
         Self {
             commands: Default::default(),
             passives: Default::default(),
+            events: Default::default(),
+            scheduler: Default::default(),
             state: Default::default(),
         }
     }
@@ -37,6 +44,8 @@ pub async fn initialize_modules<R>(init: &mut ModuleInit<R>) -> anyhow::Result<(
 where
     R: Responder + Send + 'static,
 {
+    init.state.expect_insert(Cooldowns::default())?;
+
     builtin::initialize_module(init).await?;
     link_size::initialize_module(init).await?;
     repost::initialize_module(init).await?;
@@ -47,11 +56,23 @@ where
     pictures::initialize_module(init).await?;
     hp::initialize_module(init).await?;
     gfycat::initialize_module(init).await?;
+    custom::initialize_module(init).await?;
+
+    let known = init
+        .commands
+        .modules()
+        .chain(init.passives.modules())
+        .chain(init.events.modules());
+    let filter = ModuleFilter::new(known);
+    init.state.expect_insert(filter)?;
+    let index = init.commands.index();
+    init.state.expect_insert(index)?;
+    init.state.expect_insert(init.scheduler.clone())?;
 
     let config::Web {
         listen_port,
         lookup_ip,
-    } = init.state.config().await?.web.clone();
+    } = init.state.config()?.web.clone();
     let addr = format!("0.0.0.0:{}", listen_port)
         .parse::<std::net::SocketAddr>()
         .with_context(|| "cannot parse listen address")?;
@@ -63,11 +84,29 @@ where
         .state
         .expect_get::<crate::http::server::TempStore>()?
         .clone();
+    init.scheduler.every(
+        "cull temporary files",
+        std::time::Duration::from_secs(60),
+        {
+            let temp = temp.clone();
+            move |_| {
+                let temp = temp.clone();
+                async move {
+                    temp.cull().await;
+                    Ok(())
+                }
+            }
+        },
+    )?;
+    let shutdown = init.state.expect_get::<Shutdown>()?.clone();
 
     use warp::Filter as _;
     // TODO abstract this out
     let routes = pictures::web::lookup(db).or(crate::http::server::temporary(temp));
-    tokio::spawn(warp::serve(routes).run(addr));
+    let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(addr, async move {
+        shutdown.wait().await;
+    });
+    tokio::spawn(server);
 
     Ok(())
 }
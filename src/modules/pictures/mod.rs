@@ -1,15 +1,45 @@
 use super::*;
 
+use crate::bot::cooldown::Scope;
+use crate::config::ConfigChanged;
 use rand::prelude::*;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::broadcast::{self, RecvError};
 pub mod web;
 
+/// The passive's quiet time is kept apart from the `!pictures` command's cooldowns
+const PASSIVE_COOLDOWN: &str = "pictures_passive";
+
 pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Result<()>
 where
     R: Responder + Send + 'static,
 {
+    let mapping = index(&init.state.config()?.modules.pictures.directories);
+    let db = web::Db::new(mapping);
+    init.state.expect_insert(db.clone())?;
+
+    let changes = init.state.expect_get::<CachedConfig>()?.subscribe();
+    tokio::spawn(follow_directories(changes, db));
+
+    init.state.expect_insert(LinesSeen(0))?;
+    // the passive shouldn't fire right away
+    init.state
+        .expect_get_mut::<Cooldowns>()?
+        .trigger(PASSIVE_COOLDOWN, Scope::Global);
+
+    init.commands
+        .add("pictures", pictures)?
+        .args(args())
+        .cooldown(Cooldown::default().channel(std::time::Duration::from_secs(10)))
+        .module("pictures");
+    init.passives.add("pictures", hear_passive);
+
+    Ok(())
+}
+
+fn index(directories: &HashMap<String, config::PicturesItem>) -> web::Mapping {
     let mut mapping = web::Mapping::default();
-    for (key, val) in &init.state.config().await?.modules.pictures.directories {
+    for (key, val) in directories {
         let mut entry = web::Entry::new(key);
         for channel in &val.banned_channels {
             entry.blacklist(channel);
@@ -18,20 +48,37 @@ where
         log::debug!("adding {} pictures from {} for {}", count, key, val.command);
         mapping.insert(entry);
     }
+    mapping
+}
+
+/// Indexes the directories again when they change in the config
+async fn follow_directories(mut changes: broadcast::Receiver<ConfigChanged>, db: web::Db) {
+    loop {
+        let ConfigChanged { old, new } = match changes.recv().await {
+            Ok(changed) => changed,
+            Err(RecvError::Lagged(..)) => continue,
+            Err(RecvError::Closed) => break,
+        };
 
-    init.state.expect_insert(web::Db::new(mapping))?;
-    init.state.expect_insert(LastSent {
-        instant: tokio::time::Instant::now(),
-        lines: 0,
-    })?;
+        let directories = new.modules.pictures.directories.clone();
+        if old.modules.pictures.directories == directories {
+            continue;
+        }
 
-    init.commands.add("pictures", pictures)?;
-    init.passives.add(hear_passive);
+        match tokio::task::spawn_blocking(move || index(&directories)).await {
+            Ok(mapping) => *db.inner.write().await = mapping,
+            Err(err) => log::warn!("cannot index the pictures directories: {}", err),
+        }
+    }
+}
 
-    Ok(())
+fn args() -> Args {
+    Args::new()
+        .description("lists the picture commands, or looks for new directories")
+        .subcommand("refresh", Args::new())
 }
 
-pub async fn pictures<R: Responder>(context: Context, mut responder: R) -> Result {
+pub async fn pictures<R: Responder>(context: Context, responder: R) -> Result {
     async fn list<R: Responder>(context: Context, mut responder: R) -> Result {
         let commands = {
             let state = context.state.lock().await;
@@ -102,14 +149,8 @@ pub async fn pictures<R: Responder>(context: Context, mut responder: R) -> Resul
         Ok(())
     }
 
-    let args = context.command_args();
-    match args.as_slice() {
-        ["refresh"] => refresh(context, responder).await,
-        d if !d.is_empty() => {
-            let command = args.join(" ");
-            let resp = responses::Builtin::UnknownSubcommand { command };
-            responder.reply(context, resp).await
-        }
+    match context.arguments.subcommand() {
+        Some("refresh") => refresh(context, responder).await,
         _ => list(context, responder).await,
     }
 }
@@ -141,10 +182,10 @@ pub async fn hear_passive<R: Responder>(context: Context, mut responder: R) -> R
             respond!(@inner responses::Pictures::Reply { link })
         }};
         (@inner $link:expr) => {{
-            let _ = state.insert(LastSent {
-                instant: tokio::time::Instant::now(),
-                lines: 0,
-            });
+            let _ = state.insert(LinesSeen(0));
+            state
+                .expect_get_mut::<Cooldowns>()?
+                .trigger(PASSIVE_COOLDOWN, Scope::Global);
             return responder.say(context.clone(), $link).await;
         }};
     }
@@ -156,15 +197,16 @@ pub async fn hear_passive<R: Responder>(context: Context, mut responder: R) -> R
         }
     }
 
-    let mut last = state.expect_get::<LastSent>()?.clone();
-    last.lines += 1;
-
-    if last.lines < min_lines {
-        let _ = state.insert(last);
+    let lines = state.expect_get::<LinesSeen>()?.0 + 1;
+    if lines < min_lines {
+        let _ = state.insert(LinesSeen(lines));
         return crate::util::dont_care();
     }
 
-    let left = last.instant.elapsed();
+    let left = state
+        .expect_get::<Cooldowns>()?
+        .elapsed(PASSIVE_COOLDOWN, &Scope::Global)
+        .unwrap_or_default();
     if left < tokio::time::Duration::from_secs(simple_duration_parse::parse_secs(&cooldown)?) {
         return crate::util::dont_care();
     }
@@ -193,17 +235,14 @@ pub async fn hear_passive<R: Responder>(context: Context, mut responder: R) -> R
     Ok(())
 }
 
+/// Lines seen since the passive last responded
 #[derive(Clone, Debug)]
-struct LastSent {
-    instant: tokio::time::Instant,
-    lines: usize,
-}
+struct LinesSeen(usize);
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test::*;
-    use std::collections::HashMap;
 
     #[tokio::test]
     async fn pictures() {
@@ -222,20 +261,23 @@ mod tests {
         responses.expect_empty();
 
         let responses = TestEnv::new("!pictures foobar")
+            .args(super::args())
             .execute(super::pictures)
             .await;
-        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Builtin>());
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Usage>());
         responses.expect_empty();
 
         let responses = TestEnv::new("!pictures foobar baz quux")
+            .args(super::args())
             .execute(super::pictures)
             .await;
-        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Builtin>());
+        insta::assert_yaml_snapshot!(responses.get_reply::<responses::Usage>());
         responses.expect_empty();
 
         let db = web::Db::default();
 
         let responses = TestEnv::new("!pictures refresh")
+            .args(super::args())
             .insert(db.clone())
             .config(|config| {
                 config.modules.pictures.directories = {
@@ -264,6 +306,7 @@ mod tests {
         responses.expect_empty();
 
         let responses = TestEnv::new("!pictures refresh")
+            .args(super::args())
             .insert(db.clone())
             .config(|config| {
                 config.modules.pictures.directories = {
@@ -6,16 +6,22 @@ pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Re
 where
     R: Responder + Send + 'static,
 {
-    init.commands.add("ignore", ignore_link)?;
-    init.passives.add(repost_shame);
+    init.commands
+        .add("ignore", ignore_link)?
+        .role(Role::Owner)
+        .args(
+            Args::new()
+                .description("stops a link from being counted as a repost in this channel")
+                .arg("link", Kind::Text),
+        )
+        .module("repost");
+    init.passives.add("repost", repost_shame);
     Ok(())
 }
 
 pub async fn ignore_link<R: Responder>(context: Context, mut responder: R) -> Result {
-    context.expect_owner(&mut responder).await?;
-
-    let room = context.room();
-    let channel = persist::Channel::new(room);
+    let room = context.room_key();
+    let channel = persist::Channel::new(&room);
 
     let link = match context.without_command() {
         Some(args) => args,
@@ -39,8 +45,9 @@ pub async fn repost_shame<R: Responder>(context: Context, mut responder: R) -> R
     let secs = simple_duration_parse::parse_secs(&staleness)?;
     let grace = time::Duration::seconds(secs as _);
 
-    let (nick, room) = (context.nick(), context.room());
-    let channel = persist::Channel::new(room);
+    let nick = context.nick();
+    let room = context.room_key();
+    let channel = persist::Channel::new(&room);
 
     for updated in context
         .get_links()?
@@ -43,6 +43,7 @@ async fn ignore_link() {
     let _db = crate::db::get_connection();
 
     let responses = TestEnv::new("!ignore http://example.com")
+        .requires(Role::Owner)
         .execute(super::ignore_link)
         .await;
     insta::assert_yaml_snapshot!(responses.get_reply::<responses::Builtin>());
@@ -95,3 +96,30 @@ async fn repost_shame() {
     insta::assert_yaml_snapshot!(responses.get_say::<responses::Repost>());
     responses.expect_empty();
 }
+
+#[tokio::test]
+async fn repost_networks() {
+    let _db = crate::db::get_connection();
+
+    let link = "http://example.com/networks";
+    for network in &["a", "b"] {
+        let responses = TestEnv::new(link)
+            .network(network)
+            .config(|config| config.modules.repost.staleness = "7d".into())
+            .execute(super::repost_shame)
+            .await;
+        responses.expect_empty();
+    }
+
+    let responses = TestEnv::new(link)
+        .network("a")
+        .user("foobar")
+        .config(|config| config.modules.repost.staleness = "7d".into())
+        .execute(super::repost_shame)
+        .await;
+    match responses.get_say::<responses::Repost>() {
+        responses::Repost::AlreadyPosted { count, .. } => assert_eq!(count, "1"),
+        resp => panic!("expected a repost, got {:?}", resp),
+    }
+    responses.expect_empty();
+}
@@ -9,7 +9,7 @@ pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Re
 where
     R: Responder + Send + 'static,
 {
-    init.passives.add(hear_video);
+    init.passives.add("vimeo", hear_video);
     init.state.expect_insert(client::VimeoClient::default())
 }
 
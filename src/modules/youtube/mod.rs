@@ -8,10 +8,10 @@ pub(super) async fn initialize_module<R>(init: &mut ModuleInit<R>) -> anyhow::Re
 where
     R: Responder + Send + 'static,
 {
-    init.passives.add(hear_video);
-    init.passives.add(hear_channel);
+    init.passives.add("youtube", hear_video);
+    init.passives.add("youtube", hear_channel);
 
-    let client = client::YoutubeClient::new(&init.state.config().await?.modules.youtube.api_key);
+    let client = client::YoutubeClient::new(&init.state.config()?.modules.youtube.api_key);
     init.state.expect_insert(client)
 }
 
@@ -1,11 +1,84 @@
+use crate::bot::Registry;
 use serde::{Deserialize, Serialize};
 use template::*;
 
+/// Every response, so the templates can be checked against them
+///
+/// New responses have to be added here
+pub fn registry() -> anyhow::Result<Registry> {
+    Registry::default()
+        .with::<Builtin>()?
+        .with::<Usage>()?
+        .with::<Help>()?
+        .with::<Templates>()?
+        .with::<Config>()?
+        .with::<Uptime>()?
+        .with::<Lag>()?
+        .with::<Module>()?
+        .with::<Jobs>()?
+        .with::<Custom>()?
+        .with::<LinkSize>()?
+        .with::<Repost>()?
+        .with::<Youtube>()?
+        .with::<Vimeo>()?
+        .with::<Pictures>()?
+        .with::<GDrive>()?
+        .with::<Instagram>()?
+        .with::<TempStore>()?
+        .with::<Concert>()?
+        .with::<Gfycat>()
+}
+
 #[derive(Template, Debug, Clone, Serialize, Deserialize)]
 #[namespace("builtin")]
 pub enum Builtin {
     NotOwner,
-    UnknownSubcommand { command: String },
+    NotAllowed { role: String },
+    Cooldown { remaining: String },
+}
+
+#[derive(Template, Debug, Clone, Serialize, Deserialize)]
+#[namespace("usage")]
+pub enum Usage {
+    Missing {
+        arg: String,
+        usage: String,
+    },
+    Invalid {
+        arg: String,
+        value: String,
+        kind: String,
+        usage: String,
+    },
+    TooMany {
+        usage: String,
+    },
+    UnknownSubcommand {
+        subcommand: String,
+        usage: String,
+    },
+}
+
+#[derive(Template, Debug, Clone, Serialize, Deserialize)]
+#[namespace("help")]
+pub enum Help {
+    Commands { commands: String },
+    Command { usage: String, description: String },
+    Usage { usage: String },
+    Unknown { command: String },
+}
+
+#[derive(Template, Debug, Clone, Serialize, Deserialize)]
+#[namespace("templates")]
+pub enum Templates {
+    Reloaded,
+    Invalid { error: String },
+}
+
+#[derive(Template, Debug, Clone, Serialize, Deserialize)]
+#[namespace("config")]
+pub enum Config {
+    Invalid { error: String },
 }
 
 #[derive(Template, Debug, Clone, Serialize, Deserialize)]
@@ -15,9 +88,38 @@ pub enum Uptime {
 }
 
 #[derive(Template, Debug, Clone, Serialize, Deserialize)]
-#[namespace("join")]
-pub enum Join {
-    ExpectedChannel,
+#[namespace("lag")]
+pub enum Lag {
+    Lag { lag: String },
+    Unknown,
+}
+
+#[derive(Template, Debug, Clone, Serialize, Deserialize)]
+#[namespace("module")]
+pub enum Module {
+    Enabled { module: String, channel: String },
+    Disabled { module: String, channel: String },
+    Unknown { module: String, known: String },
+}
+
+#[derive(Template, Debug, Clone, Serialize, Deserialize)]
+#[namespace("jobs")]
+pub enum Jobs {
+    Listing { jobs: String },
+    Empty,
+}
+
+#[derive(Template, Debug, Clone, Serialize, Deserialize)]
+#[namespace("custom")]
+pub enum Custom {
+    Text { text: String },
+    Added { name: String },
+    Aliased { name: String, command: String },
+    Removed { name: String },
+    NotFound { name: String },
+    Exists { name: String },
+    UnknownCommand { command: String },
+    Listing { commands: String, aliases: String },
 }
 
 #[derive(Template, Debug, Clone, Serialize, Deserialize)]
@@ -127,7 +229,6 @@ pub enum TempStore {
 #[derive(Template, Debug, Clone, Serialize, Deserialize)]
 #[namespace("concert")]
 pub enum Concert {
-    NoIdProvided,
     Title {
         id: String,
         title: String,
@@ -168,3 +269,39 @@ pub enum Gfycat {
         meta: String,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn default_templates() {
+        let path = std::env::temp_dir().join(format!("noye-defaults-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        if let Err(err) = crate::TemplateStore::new(crate::DEFAULT_TEMPLATES, &path) {
+            panic!("{:#}", err)
+        }
+    }
+
+    #[test]
+    fn registry_complete() {
+        use std::collections::BTreeSet;
+
+        // every response in this file, so one that isn't in the registry is caught
+        let declared = include_str!("responses.rs")
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("#[namespace(\""))
+            .filter_map(|line| line.split('"').next())
+            .collect::<BTreeSet<_>>();
+        assert!(!declared.is_empty());
+
+        let registry = super::registry().unwrap();
+        let registered = registry
+            .entries()
+            .map(|entry| entry.namespace())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(declared, registered);
+
+        let defaults: toml::value::Table = toml::from_str(crate::DEFAULT_TEMPLATES).unwrap();
+        let defaults = defaults.keys().map(|s| s.as_str()).collect::<BTreeSet<_>>();
+        assert_eq!(declared, defaults);
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 pub use crate::{bot::*, responses::*, *};
+// `responses::Config` shares the name
+pub use crate::Config;
 pub use futures::prelude::*;
 
 use serde::{Deserialize, Serialize};
@@ -132,7 +134,11 @@ pub struct TestEnv {
     responder: YamlResponder,
     data: String,
     sender: String,
+    account: Option<String>,
     channel: String,
+    role: Option<Role>,
+    args: Option<crate::bot::Args>,
+    network: Option<String>,
     state: Arc<Mutex<State>>,
 }
 
@@ -141,7 +147,11 @@ impl TestEnv {
         let mut config = Config::default();
         config.irc_config.name = "test_bot".into();
         config.irc_config.channels.push("#test_channel".into());
-        config.irc_config.owners.push("test_owner".into());
+        config
+            .irc_config
+            .permissions
+            .owner
+            .push("account:test_owner".into());
 
         let mut state = State::default();
         state.insert(CachedConfig::new(config, "noye.toml"));
@@ -150,27 +160,56 @@ impl TestEnv {
             responder: YamlResponder::default(),
             data: data.to_string(),
             sender: "test_user".into(),
+            account: None,
             channel: "#test_channel".into(),
+            role: None,
+            args: None,
+            network: None,
             state: Arc::new(Mutex::new(state)),
         }
     }
 
     pub fn owner(mut self) -> Self {
         self.sender = "test_owner".into();
+        self.account.replace("test_owner".into());
         self
     }
 
     pub fn user(mut self, user: impl ToString) -> Self {
         self.sender = user.to_string();
+        self.account.take();
         self
     }
 
+    /// Checks the sender has this role before the handler is called, like the dispatcher does
+    pub fn requires(mut self, role: Role) -> Self {
+        self.role.replace(role);
+        self
+    }
+
+    /// Checks the arguments against this spec before the handler is called, like the dispatcher does
+    pub fn args(mut self, args: crate::bot::Args) -> Self {
+        self.args.replace(args);
+        self
+    }
+
+    /// Sends the message directly to the bot
+    pub fn query(self) -> Self {
+        self.channel("test_bot")
+    }
+
     pub fn channel(mut self, channel: impl ToString) -> Self {
         self.channel = channel.to_string();
         self
     }
 
-    pub fn insert<T: 'static + Send + Sync>(self, item: T) -> Self {
+    /// Sends the message from this network, rather than the unnamed one
+    pub fn network(mut self, network: impl ToString) -> Self {
+        self.network.replace(network.to_string());
+        self
+    }
+
+    pub fn insert<T: 'static + Send + Sync + Clone>(self, item: T) -> Self {
         assert!(self.state.lock().now_or_never().unwrap().insert(item));
         self
     }
@@ -178,14 +217,8 @@ impl TestEnv {
     pub fn config(self, f: impl FnOnce(&mut Config)) -> Self {
         {
             let mut state = self.state.lock().now_or_never().unwrap();
-            let mut config = state
-                .expect_get_mut::<CachedConfig>()
-                .unwrap()
-                .get_mut()
-                .now_or_never()
-                .unwrap()
-                .unwrap();
-            f(&mut config);
+            let config = state.expect_get_mut::<CachedConfig>().unwrap().get_mut();
+            f(config);
         }
         self
     }
@@ -196,25 +229,66 @@ impl TestEnv {
         F: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
         F::Output: Send + 'static,
     {
+        let tags = match &self.account {
+            Some(account) => crate::irc::Tags::parse(&format!("account={}", account)),
+            None => Default::default(),
+        };
+
         let msg = crate::Message {
+            hostmask: Some(format!("{}!{}@localhost", self.sender, self.sender)),
             sender: self.sender,
             channel: self.channel,
             data: self.data,
+            tags,
+        };
+
+        // this finds the command the same way the runner does
+        let (invocation, quit) = {
+            let state = self.state.lock().await;
+            let nick = state.config().unwrap().irc_config.name.clone();
+            let invocation = crate::bot::find_invocation(&state, &msg, &nick).unwrap();
+            let quit = state.get::<Shutdown>().cloned().unwrap_or_default();
+            (invocation, quit)
         };
 
         let (tx, rx) = tokio::sync::mpsc::channel(32);
-        match handler
-            .call(
-                crate::Context {
-                    args: Arc::new(msg),
-                    writer: crate::Writer(tx),
-                    state: self.state.clone(),
-                    quit: Default::default(),
-                },
-                self.responder.clone(),
-            )
+        let mut context = crate::Context {
+            args: Arc::new(msg),
+            writer: crate::Writer(tx),
+            state: self.state.clone(),
+            quit,
+            arguments: Default::default(),
+            invocation,
+            network: self.network.map(Into::into),
+        };
+
+        let parsed = match &self.args {
+            Some(args) => {
+                let command = context.command().unwrap_or_default();
+                args.parse(command, &context.command_args()).map(Some)
+            }
+            None => Ok(None),
+        };
+
+        let mut responder = self.responder.clone();
+        let allowed = crate::bot::check_command(&context, self.role, parsed, &mut responder)
             .await
-        {
+            .map(|parsed| {
+                if let Some(parsed) = parsed {
+                    context.arguments = Arc::new(parsed);
+                }
+            });
+
+        let result = match allowed {
+            Ok(..) => handler.call(context, self.responder.clone()).await,
+            err => {
+                // the writer has to be dropped for the raw responses to be collected
+                drop(context);
+                err
+            }
+        };
+
+        match result {
             Ok(..) => {}
             Err(err) if err.is::<crate::util::DontCareSigil>() => {}
             Err(err) => {